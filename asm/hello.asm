@@ -0,0 +1,20 @@
+[bits 64]
+
+global _start
+
+SECTION .data
+msg: db "Hello, World!", 10
+len equ $ - msg
+
+SECTION .text
+_start:
+                            ; write(STDOUT, msg, len)
+  mov eax, 1                ; syscall number for WRITE
+  mov edi, 1                ; STDOUT
+  mov esi, msg
+  mov edx, len
+  int 0x80
+                            ; exit(0)
+  mov eax, 5                ; syscall number for EXIT
+  xor edi, edi              ; no error
+  int 0x80
@@ -6,6 +6,13 @@ global syscall_read
 global syscall_open
 global syscall_close
 global syscall_sleep
+global syscall_exit
+global syscall_fork
+global syscall_shm_create
+global syscall_shm_map
+global syscall_shm_unlink
+global syscall_mmap
+global syscall_munmap
 
 %define SYSCALL_OPEN 0
 %define SYSCALL_WRITE 1
@@ -13,6 +20,14 @@ global syscall_sleep
 %define SYSCALL_CLOSE 3
 
 %define SYSCALL_SLEEP 4
+%define SYSCALL_EXIT 5
+%define SYSCALL_FORK 6
+
+%define SYSCALL_SHM_CREATE 7
+%define SYSCALL_SHM_MAP 8
+%define SYSCALL_SHM_UNLINK 9
+%define SYSCALL_MMAP 10
+%define SYSCALL_MUNMAP 11
 
 
 section .text
@@ -37,7 +52,41 @@ syscall_close:
     ret
 
 syscall_sleep:
-    mov rax, SYSCALL_CLOSE
+    mov rax, SYSCALL_SLEEP
+    int 0x80
+    ret
+
+syscall_exit:
+    mov rax, SYSCALL_EXIT
+    int 0x80
+    ret
+
+syscall_fork:
+    mov rax, SYSCALL_FORK
+    int 0x80
+    ret
+
+syscall_shm_create:
+    mov rax, SYSCALL_SHM_CREATE
     int 0x80
     ret
 
+syscall_shm_map:
+    mov rax, SYSCALL_SHM_MAP
+    int 0x80
+    ret
+
+syscall_shm_unlink:
+    mov rax, SYSCALL_SHM_UNLINK
+    int 0x80
+    ret
+
+syscall_mmap:
+    mov rax, SYSCALL_MMAP
+    int 0x80
+    ret
+
+syscall_munmap:
+    mov rax, SYSCALL_MUNMAP
+    int 0x80
+    ret
@@ -2,4 +2,11 @@ extern syscall_sleep;
 extern syscall_write;
 extern syscall_read;
 extern syscall_open;
-extern syscall_close;
\ No newline at end of file
+extern syscall_close;
+extern syscall_exit;
+extern syscall_fork;
+extern syscall_shm_create;
+extern syscall_shm_map;
+extern syscall_shm_unlink;
+extern syscall_mmap;
+extern syscall_munmap;
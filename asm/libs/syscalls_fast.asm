@@ -0,0 +1,94 @@
+[bits 64]
+; Same Interface As syscalls.asm, But Enters The Kernel With `syscall`.
+; RCX & R11 Are Clobbered, Both Are Caller-Saved In The System V ABI.
+
+; Symbol Exports
+global syscall_write
+global syscall_read
+global syscall_open
+global syscall_close
+global syscall_sleep
+global syscall_exit
+global syscall_fork
+global syscall_shm_create
+global syscall_shm_map
+global syscall_shm_unlink
+global syscall_mmap
+global syscall_munmap
+
+%define SYSCALL_OPEN 0
+%define SYSCALL_WRITE 1
+%define SYSCALL_READ 2
+%define SYSCALL_CLOSE 3
+
+%define SYSCALL_SLEEP 4
+%define SYSCALL_EXIT 5
+%define SYSCALL_FORK 6
+
+%define SYSCALL_SHM_CREATE 7
+%define SYSCALL_SHM_MAP 8
+%define SYSCALL_SHM_UNLINK 9
+%define SYSCALL_MMAP 10
+%define SYSCALL_MUNMAP 11
+
+
+section .text
+syscall_write:
+    mov rax, SYSCALL_WRITE
+    syscall
+    ret
+
+syscall_read:
+    mov rax, SYSCALL_READ
+    syscall
+    ret
+
+syscall_open:
+    mov rax, SYSCALL_OPEN
+    syscall
+    ret
+
+syscall_close:
+    mov rax, SYSCALL_CLOSE
+    syscall
+    ret
+
+syscall_sleep:
+    mov rax, SYSCALL_SLEEP
+    syscall
+    ret
+
+syscall_exit:
+    mov rax, SYSCALL_EXIT
+    syscall
+    ret
+
+syscall_fork:
+    mov rax, SYSCALL_FORK
+    syscall
+    ret
+
+syscall_shm_create:
+    mov rax, SYSCALL_SHM_CREATE
+    syscall
+    ret
+
+syscall_shm_map:
+    mov rax, SYSCALL_SHM_MAP
+    syscall
+    ret
+
+syscall_shm_unlink:
+    mov rax, SYSCALL_SHM_UNLINK
+    syscall
+    ret
+
+syscall_mmap:
+    mov rax, SYSCALL_MMAP
+    syscall
+    ret
+
+syscall_munmap:
+    mov rax, SYSCALL_MUNMAP
+    syscall
+    ret
@@ -10,6 +10,5 @@ _start:
   mov rdi, __float64__(5.0) ; time to sleep in seconds
   call syscall_sleep
                             ; exit(0)
-  mov rax, 1                ; syscall number for EXIT
   mov rdi, 0                ; no error
-  int 0x80
\ No newline at end of file
+  call syscall_exit
\ No newline at end of file
@@ -24,3 +24,25 @@ timer_period_t timer_period(timer_t timer);
 
 void sleep(double seconds); 
 
+// ========= Processes ========= 
+void exit(int code);
+int  fork(void);
+
+// ========= Mapped Files ========= 
+/*
+    Maps A File Of The Mounted Disk At The Page Aligned `addr`, Returns The
+    Length Of The Mapping. Changes Are Written Back By `munmap` & On Exit
+*/
+size_t mmap  (cstr_t path, void* addr, int writable);
+int    munmap(void* addr);
+
+// ========= Shared Memory ========= 
+/*
+    Creates A Named Region Of At Least `size` Bytes, Returns Its Size
+*/
+size_t shm_create(cstr_t name, size_t size);
+/*
+    Maps The Whole Region At The Page Aligned `addr`, Returns Its Size
+*/
+size_t shm_map   (cstr_t name, void* addr, int writable);
+int    shm_unlink(cstr_t name);
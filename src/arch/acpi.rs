@@ -1,6 +1,6 @@
 use core::ptr::NonNull;
 
-use acpi::{AcpiHandler, AcpiTables, PhysicalMapping};
+use acpi::{AcpiHandler, AcpiTables, HpetInfo, PhysicalMapping};
 use alloc::boxed::Box;
 use aml::{AmlContext, Handler};
 use x86_64::{instructions::port::Port, PhysAddr};
@@ -22,13 +22,24 @@ enum FADT {
     Pm1bEventBlock = 60,   // u32,
     Pm1aControlBlock = 64, // u32,
     Pm1bControlBlock = 68, // u32,
+    Flags = 112,           // u32,
+    ResetRegister = 116,   // u8, Address Space Of The Generic Address
+    ResetAddress = 120,    // u64,
+    ResetValue = 128,      // u8,
 }
 
+/// FADT Flag Set When The Reset Register Is Supported.
+const RESET_REG_SUP: u32 = 1 << 10;
+
+/// Generic Address Spaces The Reset Register Can Be In.
+const SPACE_MEMORY: u8 = 0;
+const SPACE_IO: u8 = 1;
+
 fn read_addr<T: Copy>(physical_address: usize) -> T {
     let virtual_address = crate::mem::phys_to_virt(
         PhysAddr::new(physical_address as u64)
     ).unwrap();
-    unsafe { *virtual_address.as_ptr::<T>() }
+    unsafe { virtual_address.as_ptr::<T>().read_unaligned() }
 }
 
 fn write_addr<T: Copy>(physical_address: usize, item: T) {
@@ -91,6 +102,49 @@ pub fn shutdown() {
     }
 }
 
+/// Physical Address Of The HPET's Registers, If The ACPI Tables List One.
+pub fn hpet_address() -> Option<PhysAddr> {
+    let tables = unsafe { AcpiTables::search_for_rsdp_bios(CashewAcpiHandler) }.ok()?;
+    let info = HpetInfo::new(&tables).ok()?;
+    Some(PhysAddr::new(info.base_address as u64))
+}
+
+/// Resets The Machine Through The FADT Reset Register. Returns If The
+/// Firmware Has No Reset Register Or Writing It Did Nothing.
+pub fn reset() {
+    let acpi = match unsafe { AcpiTables::search_for_rsdp_bios(CashewAcpiHandler) } {
+        Ok(acpi) => acpi,
+        Err(_e) => {
+            kerr!("Failed To Read RSDP Table\n");
+            return;
+        }
+    };
+
+    let fadt = match acpi.sdts.iter().find(|(sig, _)| sig.as_str() == "FACP") {
+        Some((_, sdt)) => sdt.physical_address,
+        None => return,
+    };
+    // ACPI 1.0 FADTs End Before The Reset Register.
+    let length = read_addr::<u32>(fadt + 4) as usize;
+    if length <= FADT::ResetValue as usize
+        || read_fadt::<u32>(fadt, FADT::Flags) & RESET_REG_SUP == 0
+    {
+        klog!("ACPI Reset Register Unsupported\n");
+        return;
+    }
+
+    let space = read_fadt::<u8>(fadt, FADT::ResetRegister);
+    let address = read_fadt::<u64>(fadt, FADT::ResetAddress);
+    let value = read_fadt::<u8>(fadt, FADT::ResetValue);
+    match space {
+        SPACE_MEMORY => write_addr(address as usize, value),
+        SPACE_IO => unsafe { Port::<u8>::new(address as u16).write(value) },
+        _ => {
+            klog!("ACPI Reset Register In Unsupported Address Space {}\n", space);
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct CashewAcpiHandler;
 
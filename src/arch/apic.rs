@@ -0,0 +1,385 @@
+//! Local & I/O APIC Interrupt Routing.
+//!
+//! The MADT Lists The Local APIC & Every I/O APIC. ISA IRQs Are Routed
+//! Through The I/O APICs To The Same Vectors The 8259 Used (`PIC1 + IRQ`),
+//! Following The Interrupt Source Overrides, So Handlers Don't Change. They
+//! Start Masked Until [`route_isa`] Unmasks Them. Once The APIC Is Enabled
+//! The 8259 Is Masked & Interrupts Are Acknowledged With [`eoi`].
+use acpi::{
+    platform::{
+        interrupt::{Apic, InterruptSourceOverride, Polarity, TriggerMode},
+        ProcessorInfo, ProcessorState,
+    },
+    AcpiTables, InterruptModel,
+};
+use alloc::vec::Vec;
+use conquer_once::spin::OnceCell;
+use core::sync::atomic::{AtomicU32, Ordering};
+use x86_64::{instructions::interrupts, registers::model_specific::Msr, PhysAddr, VirtAddr};
+
+use crate::{kerr, klog, locked::Locked, mem::dma, pit};
+
+use super::{
+    acpi::CashewAcpiHandler,
+    pic::{self, IrqIndex, PIC1},
+};
+
+/// Delivered When An Interrupt Disappears Before It Is Accepted, Needs No EOI.
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+const IA32_APIC_BASE: u32 = 0x1B;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+// Local APIC Registers, As Offsets From Its Base.
+const LAPIC_ID: usize = 0x20;
+const LAPIC_TPR: usize = 0x80;
+const LAPIC_EOI: usize = 0xB0;
+const LAPIC_SVR: usize = 0xF0;
+const LAPIC_ICR_LOW: usize = 0x300;
+const LAPIC_ICR_HIGH: usize = 0x310;
+const LAPIC_LVT_TIMER: usize = 0x320;
+const LAPIC_TIMER_INITIAL: usize = 0x380;
+const LAPIC_TIMER_CURRENT: usize = 0x390;
+const LAPIC_TIMER_DIVIDE: usize = 0x3E0;
+
+const SVR_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_PERIODIC: u32 = 1 << 17;
+const ICR_INIT: u32 = 0b101 << 8;
+const ICR_STARTUP: u32 = 0b110 << 8;
+const ICR_NMI: u32 = 0b100 << 8;
+const ICR_PENDING: u32 = 1 << 12;
+const ICR_ASSERT: u32 = 1 << 14;
+
+/// Divide The Bus Clock By 16.
+const TIMER_DIVIDE_16: u32 = 0b0011;
+
+// I/O APIC Registers, Reached Through `IOREGSEL` & `IOWIN`.
+const IOAPIC_VERSION: u32 = 0x01;
+const IOAPIC_REDIRECTION: u32 = 0x10;
+
+const REDIRECT_ACTIVE_LOW: u64 = 1 << 13;
+const REDIRECT_LEVEL: u64 = 1 << 15;
+const REDIRECT_MASKED: u64 = 1 << 16;
+
+/// ISA IRQs Routed At Boot, Matching The 8259's Lines.
+const ISA_IRQS: u8 = 16;
+
+/// The Local APIC's Registers, Identical On Every CPU.
+static LAPIC: OnceCell<VirtAddr> = OnceCell::uninit();
+static IO_APICS: OnceCell<Locked<Vec<IoApic>>> = OnceCell::uninit();
+/// Local APIC IDs Of The Other CPUs That Can Be Started.
+static APPLICATION_PROCESSORS: OnceCell<Vec<u8>> = OnceCell::uninit();
+/// Where Each ISA IRQ Ended Up, Indexed By IRQ. `None` If Another IRQ Was
+/// Moved Onto Its Line.
+static ISA_ROUTES: OnceCell<[Option<Route>; ISA_IRQS as usize]> = OnceCell::uninit();
+
+/// Local APIC Timer Ticks Per Millisecond, 0 Until Calibrated.
+static TIMER_TICKS_PER_MS: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Debug, Clone, Copy)]
+struct Route {
+    gsi: u32,
+    /// Redirection Entry Flags Other Than The Vector & Mask.
+    flags: u64,
+}
+
+struct IoApic {
+    base: VirtAddr,
+    /// First Global System Interrupt Handled By This I/O APIC.
+    gsi_base: u32,
+    entries: u32,
+}
+
+impl IoApic {
+    fn new(phys: u32, gsi_base: u32) -> Self {
+        let base = dma::map_mmio(PhysAddr::new(phys as u64), 0x20);
+        let mut io_apic = Self {
+            base,
+            gsi_base,
+            entries: 0,
+        };
+        io_apic.entries = ((io_apic.read(IOAPIC_VERSION) >> 16) & 0xFF) + 1;
+        io_apic
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        (self.gsi_base..self.gsi_base + self.entries).contains(&gsi)
+    }
+
+    fn read(&mut self, register: u32) -> u32 {
+        unsafe {
+            self.base.as_mut_ptr::<u32>().write_volatile(register);
+            (self.base + 0x10u64).as_ptr::<u32>().read_volatile()
+        }
+    }
+
+    fn write(&mut self, register: u32, value: u32) {
+        unsafe {
+            self.base.as_mut_ptr::<u32>().write_volatile(register);
+            (self.base + 0x10u64).as_mut_ptr::<u32>().write_volatile(value);
+        }
+    }
+
+    fn set_redirection(&mut self, gsi: u32, entry: u64) {
+        let register = IOAPIC_REDIRECTION + (gsi - self.gsi_base) * 2;
+        self.write(register, entry as u32);
+        self.write(register + 1, (entry >> 32) as u32);
+    }
+
+    fn redirection(&mut self, gsi: u32) -> u64 {
+        let register = IOAPIC_REDIRECTION + (gsi - self.gsi_base) * 2;
+        self.read(register) as u64 | (self.read(register + 1) as u64) << 32
+    }
+}
+
+/// Switches From The 8259 To The APIC If The MADT Describes One, Returning
+/// `false` & Leaving The 8259 In Charge Otherwise.
+pub fn initialize() -> bool {
+    let (apic, processors) = match read_madt() {
+        Some(madt) => madt,
+        None => {
+            klog!("No APIC In The MADT, Using The 8259\n");
+            return false;
+        }
+    };
+
+    interrupts::without_interrupts(|| {
+        LAPIC.init_once(|| dma::map_mmio(PhysAddr::new(apic.local_apic_address), 0x400));
+        IO_APICS.init_once(|| {
+            let io_apics = apic
+                .io_apics
+                .iter()
+                .map(|io_apic| IoApic::new(io_apic.address, io_apic.global_system_interrupt_base))
+                .collect();
+            Locked::new(io_apics)
+        });
+        ISA_ROUTES.init_once(|| {
+            let mut routes = [None; ISA_IRQS as usize];
+            for (irq, route) in routes.iter_mut().enumerate() {
+                *route = isa_route(irq as u8, &apic.interrupt_source_overrides);
+            }
+            routes
+        });
+
+        APPLICATION_PROCESSORS.init_once(|| {
+            let processors = processors.map(|info| info.application_processors);
+            processors
+                .unwrap_or_default()
+                .iter()
+                .filter(|cpu| cpu.state != ProcessorState::Disabled)
+                .map(|cpu| cpu.local_apic_id as u8)
+                .collect()
+        });
+
+        enable_local();
+        for irq in 0..ISA_IRQS {
+            route_isa(irq, PIC1 + irq, true);
+        }
+        pic::disable();
+    });
+
+    klog!(
+        "APIC: Local APIC {} & {} I/O APIC(s)\n",
+        local_id(),
+        apic.io_apics.len()
+    );
+    true
+}
+
+fn read_madt() -> Option<(Apic, Option<ProcessorInfo>)> {
+    let tables = match unsafe { AcpiTables::search_for_rsdp_bios(CashewAcpiHandler) } {
+        Ok(tables) => tables,
+        Err(_) => {
+            kerr!("Failed To Read RSDP Table\n");
+            return None;
+        }
+    };
+
+    let info = tables.platform_info().ok()?;
+    match info.interrupt_model {
+        InterruptModel::Apic(apic) if !apic.io_apics.is_empty() => {
+            Some((apic, info.processor_info))
+        }
+        _ => None,
+    }
+}
+
+/// The GSI & Flags For ISA `irq`, Identity Mapped, Edge Triggered & Active
+/// High Unless An Override Says Otherwise.
+fn isa_route(irq: u8, overrides: &[InterruptSourceOverride]) -> Option<Route> {
+    let source = match overrides.iter().find(|o| o.isa_source == irq) {
+        Some(source) => source,
+        None if overrides.iter().any(|o| o.global_system_interrupt == irq as u32) => {
+            return None
+        }
+        None => {
+            return Some(Route {
+                gsi: irq as u32,
+                flags: 0,
+            })
+        }
+    };
+
+    let mut flags = 0;
+    if let Polarity::ActiveLow = source.polarity {
+        flags |= REDIRECT_ACTIVE_LOW;
+    }
+    if let TriggerMode::Level = source.trigger_mode {
+        flags |= REDIRECT_LEVEL;
+    }
+    Some(Route {
+        gsi: source.global_system_interrupt,
+        flags,
+    })
+}
+
+/// Enables The Local APIC Of The Calling CPU & Accepts Every Priority.
+pub fn enable_local() {
+    unsafe {
+        let mut base = Msr::new(IA32_APIC_BASE);
+        let value = base.read();
+        base.write(value | APIC_BASE_ENABLE);
+    }
+
+    write_local(LAPIC_TPR, 0);
+    write_local(LAPIC_SVR, SVR_ENABLE | SPURIOUS_VECTOR as u32);
+}
+
+pub fn is_enabled() -> bool {
+    LAPIC.get().is_some()
+}
+
+/// The ID Of The Calling CPU's Local APIC.
+pub fn local_id() -> u8 {
+    (read_local(LAPIC_ID) >> 24) as u8
+}
+
+/// The Local APIC IDs Of Every Other Usable CPU Listed In The MADT.
+pub fn application_processors() -> &'static [u8] {
+    APPLICATION_PROCESSORS.get().map_or(&[], Vec::as_slice)
+}
+
+/// Sends An INIT IPI, Resetting The CPU With Local APIC `apic_id`.
+pub fn send_init(apic_id: u8) {
+    send_ipi(apic_id, ICR_INIT | ICR_ASSERT);
+}
+
+/// Sends A Startup IPI, Starting The CPU In Real Mode At `page * 4096`.
+pub fn send_startup(apic_id: u8, page: u8) {
+    send_ipi(apic_id, ICR_STARTUP | ICR_ASSERT | page as u32);
+}
+
+/// Sends A Non-Maskable Interrupt, Which Arrives Even With Interrupts Off.
+pub fn send_nmi(apic_id: u8) {
+    send_ipi(apic_id, ICR_NMI | ICR_ASSERT);
+}
+
+fn send_ipi(apic_id: u8, command: u32) {
+    write_local(LAPIC_ICR_HIGH, (apic_id as u32) << 24);
+    write_local(LAPIC_ICR_LOW, command);
+    while read_local(LAPIC_ICR_LOW) & ICR_PENDING != 0 {
+        core::hint::spin_loop();
+    }
+}
+
+/// Acknowledges The Interrupt Being Handled.
+pub fn eoi() {
+    write_local(LAPIC_EOI, 0);
+}
+
+/// Sends ISA `irq` To `vector` On This CPU, Masked If `masked`.
+pub fn route_isa(irq: IrqIndex, vector: u8, masked: bool) {
+    let route = match ISA_ROUTES.get() {
+        Some(routes) if irq < ISA_IRQS => match routes[irq as usize] {
+            Some(route) => route,
+            None => return,
+        },
+        _ => return,
+    };
+
+    let mut entry = route.flags | vector as u64 | (local_id() as u64) << 56;
+    if masked {
+        entry |= REDIRECT_MASKED;
+    }
+    with_io_apic(route.gsi, |io_apic| io_apic.set_redirection(route.gsi, entry));
+}
+
+/// Masks Or Unmasks Global System Interrupt `gsi`.
+pub fn set_masked(gsi: u32, masked: bool) {
+    with_io_apic(gsi, |io_apic| {
+        let entry = io_apic.redirection(gsi) & !REDIRECT_MASKED;
+        io_apic.set_redirection(gsi, entry | if masked { REDIRECT_MASKED } else { 0 });
+    });
+}
+
+/// The Global System Interrupt ISA `irq` Is Wired To.
+pub fn isa_gsi(irq: IrqIndex) -> Option<u32> {
+    Some(ISA_ROUTES.get()?.get(irq as usize).copied()??.gsi)
+}
+
+fn with_io_apic(gsi: u32, f: impl FnOnce(&mut IoApic)) {
+    if let Some(io_apics) = IO_APICS.get() {
+        interrupts::without_interrupts(|| {
+            if let Some(io_apic) = io_apics.lock().iter_mut().find(|io| io.handles(gsi)) {
+                f(io_apic);
+            }
+        });
+    }
+}
+
+/// Starts The Local APIC Timer Of The Calling CPU, Raising `vector` Every
+/// `millis` Milliseconds. Calibrated Against The PIT On First Use.
+pub fn start_timer(vector: u8, millis: u32) {
+    let ticks = match TIMER_TICKS_PER_MS.load(Ordering::Relaxed) {
+        0 => calibrate_timer(),
+        ticks => ticks,
+    };
+
+    write_local(LAPIC_TIMER_DIVIDE, TIMER_DIVIDE_16);
+    write_local(LAPIC_LVT_TIMER, vector as u32 | LVT_PERIODIC);
+    write_local(LAPIC_TIMER_INITIAL, ticks.saturating_mul(millis).max(1));
+}
+
+pub fn stop_timer() {
+    write_local(LAPIC_LVT_TIMER, LVT_MASKED);
+    write_local(LAPIC_TIMER_INITIAL, 0);
+}
+
+/// Counts Timer Ticks Over 10 PIT Ticks, Which Must Be Arriving.
+fn calibrate_timer() -> u32 {
+    const PIT_TICKS: u64 = 10;
+
+    write_local(LAPIC_TIMER_DIVIDE, TIMER_DIVIDE_16);
+    write_local(LAPIC_LVT_TIMER, LVT_MASKED);
+
+    // Start On A Tick Edge.
+    let start = pit::ticks();
+    while pit::ticks() == start {
+        interrupts::enable_and_hlt();
+    }
+
+    write_local(LAPIC_TIMER_INITIAL, u32::MAX);
+    let start = pit::ticks();
+    while pit::ticks() - start < PIT_TICKS {
+        interrupts::enable_and_hlt();
+    }
+    let elapsed = u32::MAX - read_local(LAPIC_TIMER_CURRENT);
+    write_local(LAPIC_TIMER_INITIAL, 0);
+
+    let millis = PIT_TICKS * 1000 / pit::polling_rate();
+    let ticks = (elapsed as u64 / millis.max(1)) as u32;
+    TIMER_TICKS_PER_MS.store(ticks, Ordering::Relaxed);
+    klog!("APIC: Timer Runs At {} Ticks/ms\n", ticks);
+    ticks
+}
+
+fn read_local(register: usize) -> u32 {
+    let base = LAPIC.get().expect("Local APIC Not Initialized");
+    unsafe { (*base + register).as_ptr::<u32>().read_volatile() }
+}
+
+fn write_local(register: usize, value: u32) {
+    let base = LAPIC.get().expect("Local APIC Not Initialized");
+    unsafe { (*base + register).as_mut_ptr::<u32>().write_volatile(value) }
+}
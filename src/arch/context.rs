@@ -0,0 +1,185 @@
+use core::arch::{asm, global_asm};
+
+use x86_64::instructions::segmentation::{Segment, CS, SS};
+
+use super::gdt;
+
+/// RFLAGS With Interrupts Enabled (Bit 1 Is Reserved And Always Set).
+pub const RFLAGS_DEFAULT: u64 = 0x202;
+
+/// The Register State Saved By The Switch Stubs.
+///
+/// The General Purpose Registers Are Pushed By The Stub, The Remaining
+/// Fields Are The Interrupt Frame Pushed By The CPU.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Context {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl Context {
+    /// Builds The Initial Context Of A Kernel Thread, `arg` Is Passed In RDI.
+    pub fn kernel(entry: u64, stack_pointer: u64, arg: u64) -> Self {
+        Self {
+            rip: entry,
+            cs: CS::get_reg().0 as u64,
+            rflags: RFLAGS_DEFAULT,
+            rsp: stack_pointer,
+            ss: SS::get_reg().0 as u64,
+            rdi: arg,
+            ..Default::default()
+        }
+    }
+}
+
+/// Drops To Ring 3 At `entry` With The Stack Pointer Set To `stack_pointer`,
+/// Swapping Out The Kernel's GS Base Like Every Return To Ring 3.
+///
+/// ## Safety
+/// - `entry` & The Stack Must Be Mapped `USER_ACCESSIBLE`, And The TSS's
+///   RSP0 Must Point At The Calling Thread's Kernel Stack.
+pub unsafe fn enter_user(entry: u64, stack_pointer: u64) -> ! {
+    let selectors = gdt::selectors();
+    let code = (selectors.user_code.0 | 3) as u64;
+    let data = (selectors.user_data.0 | 3) as u64;
+    asm!(
+        "push {data}",
+        "push {stack}",
+        "push {rflags}",
+        "push {code}",
+        "push {entry}",
+        "xor rbp, rbp",
+        "swapgs",
+        "iretq",
+        data = in(reg) data,
+        stack = in(reg) stack_pointer,
+        rflags = in(reg) RFLAGS_DEFAULT,
+        code = in(reg) code,
+        entry = in(reg) entry,
+        options(noreturn)
+    )
+}
+
+/// Returns To Ring 3 With Every Register Set From `context`, Used To Start A
+/// Forked Process Where Its Parent Made The System Call.
+///
+/// ## Safety
+/// - `context` Must Describe A Ring 3 Context Valid In The Active Address
+///   Space, And The TSS's RSP0 Must Point At The Calling Thread's Kernel Stack.
+pub unsafe fn resume_user(context: &Context) -> ! {
+    asm!(
+        "mov rsp, {context}",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rbp",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rbx",
+        "pop rax",
+        "swapgs",
+        "iretq",
+        context = in(reg) context as *const Context,
+        options(noreturn)
+    )
+}
+
+/// Generates An Interrupt Entry Point That Saves A `Context`, Passes It To
+/// `$handler` And Resumes Whichever Context The Handler Returns. GS Is
+/// Swapped When The Interrupted Or Resumed Context Is In Ring 3.
+macro_rules! switch_stub {
+    ($name:literal, $handler:literal) => {
+        global_asm!(concat!(
+            ".global ", $name, "\n",
+            $name, ":\n",
+            "    test qword ptr [rsp + 8], 3\n",
+            "    jz 2f\n",
+            "    swapgs\n",
+            "2:\n",
+            "    push rax\n",
+            "    push rbx\n",
+            "    push rcx\n",
+            "    push rdx\n",
+            "    push rsi\n",
+            "    push rdi\n",
+            "    push rbp\n",
+            "    push r8\n",
+            "    push r9\n",
+            "    push r10\n",
+            "    push r11\n",
+            "    push r12\n",
+            "    push r13\n",
+            "    push r14\n",
+            "    push r15\n",
+            "    mov rdi, rsp\n",
+            "    call ", $handler, "\n",
+            "    mov rsp, rax\n",
+            "    pop r15\n",
+            "    pop r14\n",
+            "    pop r13\n",
+            "    pop r12\n",
+            "    pop r11\n",
+            "    pop r10\n",
+            "    pop r9\n",
+            "    pop r8\n",
+            "    pop rbp\n",
+            "    pop rdi\n",
+            "    pop rsi\n",
+            "    pop rdx\n",
+            "    pop rcx\n",
+            "    pop rbx\n",
+            "    pop rax\n",
+            "    test qword ptr [rsp + 8], 3\n",
+            "    jz 3f\n",
+            "    swapgs\n",
+            "3:\n",
+            "    iretq\n",
+        ));
+    };
+}
+
+switch_stub!("timer_entry", "timer_switch");
+switch_stub!("local_timer_entry", "local_timer_switch");
+switch_stub!("yield_entry", "yield_switch");
+switch_stub!("syscall_entry", "syscall_switch");
+
+extern "C" {
+    pub fn timer_entry();
+    pub fn local_timer_entry();
+    pub fn yield_entry();
+    pub fn syscall_entry();
+}
+
+/// Enters The Scheduler Through The Yield Vector (`idt::YIELD_VECTOR`).
+pub fn yield_cpu() {
+    unsafe {
+        asm!("int 0x81");
+    }
+}
@@ -1,5 +1,5 @@
 use alloc::string::String;
-use raw_cpuid::CpuId;
+use raw_cpuid::{CpuId, TopologyType};
 
 
 #[repr(C)]
@@ -40,6 +40,14 @@ pub fn supports_avx() -> bool {
         .map_or(false, |finfo| finfo.has_avx())
 }
 
+/// Whether The TSC Ticks At A Constant Rate Through Frequency & Power
+/// State Changes, So It Can Keep Time.
+pub fn has_invariant_tsc() -> bool {
+    cpuid()
+        .get_advanced_power_mgmt_info()
+        .is_some_and(|info| info.has_invariant_tsc())
+}
+
 pub fn cache_params() -> Option<raw_cpuid::CacheParametersIter> {
     cpuid().get_cache_parameters()
 }
@@ -48,3 +56,37 @@ pub fn l1_cache_info() -> Option<raw_cpuid::CacheParameter> {
     cache_params()
     .and_then(|mut params| {params.nth(0)})
 }
+
+/// Where The Calling CPU Sits In Its Package.
+#[derive(Debug, Clone, Copy)]
+pub struct Topology {
+    pub apic_id: u32,
+    /// Logical Processors Sharing A Core.
+    pub threads_per_core: u16,
+    /// Logical Processors Sharing A Package.
+    pub threads_per_package: u16,
+}
+
+/// Reads The Topology From CPUID Leaf 0xB, Falling Back To Leaf 1 (One
+/// Thread Per Core) Without It.
+pub fn topology() -> Topology {
+    let cpuid = cpuid();
+    let features = cpuid.get_feature_info();
+    let mut topology = Topology {
+        apic_id: features.as_ref().map_or(0, |f| f.initial_local_apic_id() as u32),
+        threads_per_core: 1,
+        threads_per_package: features.map_or(1, |f| f.max_logical_processor_ids().max(1) as u16),
+    };
+
+    if let Some(levels) = cpuid.get_extended_topology_info() {
+        for level in levels {
+            match level.level_type() {
+                TopologyType::SMT => topology.threads_per_core = level.processors(),
+                TopologyType::Core => topology.threads_per_package = level.processors(),
+                _ => {}
+            }
+            topology.apic_id = level.x2apic_id();
+        }
+    }
+    topology
+}
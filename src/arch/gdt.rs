@@ -1,3 +1,4 @@
+use alloc::{boxed::Box, vec};
 use lazy_static::lazy_static;
 use x86_64::{
     instructions::{segmentation::*, tables::load_tss},
@@ -5,51 +6,128 @@ use x86_64::{
     VirtAddr,
 };
 
-use crate::{println, sprint};
+use crate::sprint;
+
+use super::smp;
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+/// NMIs Can Arrive Right After `syscall`, Before RSP Points At A Kernel
+/// Stack, So They Always Switch To A Stack Of Their Own.
+pub const NMI_IST_INDEX: u16 = 1;
 
-static mut STACK: [u8; 4096 * 5] = [0; 4096 * 5];
-static mut _USR_STACK: [u8; 4096 * 5] = [0; 4096 * 5];
+const STACK_SIZE: usize = 4096 * 5;
 
-lazy_static! {
+static mut DOUBLE_FAULT_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+static mut NMI_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+/// Used As RSP0 Until The Scheduler Switches To A Thread With Its Own Stack.
+static mut PRIVILEGE_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
 
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
+/// The TSS Is Mutable So RSP0 Can Follow The Running Thread's Kernel Stack.
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
 
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + (4096 * 5) as u64;
-            stack_end
-        };
-        tss
-    };
+#[derive(Debug, Clone, Copy)]
+pub struct Selectors {
+    pub kernel_code: SegmentSelector,
+    pub kernel_data: SegmentSelector,
+    pub user_data: SegmentSelector,
+    pub user_code: SegmentSelector,
+    pub tss: SegmentSelector,
+}
+
+lazy_static! {
+    // The Order Of The Kernel & User Segments Is Fixed By SYSCALL/SYSRET,
+    // Which Derive Them From A Single Base Selector.
+    static ref GDT: (GlobalDescriptorTable, Selectors) = {
+        unsafe {
+            TSS.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = stack_top(&DOUBLE_FAULT_STACK);
+            TSS.interrupt_stack_table[NMI_IST_INDEX as usize] = stack_top(&NMI_STACK);
+            TSS.privilege_stack_table[0] = stack_top(&PRIVILEGE_STACK);
+        }
 
-    static ref GDT: (GlobalDescriptorTable, [SegmentSelector; 5]) = {
         let mut gdt = GlobalDescriptorTable::new();
-        let kernel_data_flags = DescriptorFlags::USER_SEGMENT | DescriptorFlags::PRESENT | DescriptorFlags::WRITABLE;
-        let code_sel = gdt.add_entry(Descriptor::kernel_code_segment()); // kernel code segment
-        let data_sel = gdt.add_entry(Descriptor::UserSegment(kernel_data_flags.bits())); // kernel data segment
-        let tss_sel = gdt.add_entry(Descriptor::tss_segment(&TSS)); // task state segment
-        let user_data_sel = gdt.add_entry(Descriptor::user_data_segment()); // user data segment
-        let user_code_sel = gdt.add_entry(Descriptor::user_code_segment()); // user code segment
-        (gdt, [code_sel, data_sel, tss_sel, user_data_sel, user_code_sel])
+        let kernel_code = gdt.add_entry(Descriptor::kernel_code_segment());
+        let kernel_data = gdt.add_entry(Descriptor::kernel_data_segment());
+        let user_data = gdt.add_entry(Descriptor::user_data_segment());
+        let user_code = gdt.add_entry(Descriptor::user_code_segment());
+        let tss = gdt.add_entry(Descriptor::tss_segment(unsafe { &TSS }));
+        (gdt, Selectors { kernel_code, kernel_data, user_data, user_code, tss })
     };
 }
 
-pub fn _init_gdt() {
+fn stack_top(stack: &[u8; STACK_SIZE]) -> VirtAddr {
+    (VirtAddr::from_ptr(stack) + STACK_SIZE).align_down(16u64)
+}
+
+/// Allocates A Stack That Is Never Freed, Returning Its Top.
+fn leak_stack() -> VirtAddr {
+    let stack: &'static mut [u8] = Box::leak(vec![0; STACK_SIZE].into_boxed_slice());
+    (VirtAddr::from_ptr(stack.as_ptr()) + STACK_SIZE).align_down(16u64)
+}
+
+pub fn init() {
     sprint!("Loading GDT\n");
     GDT.0.load();
-    let stack = unsafe { &STACK as *const _ };
-    let user_stack = unsafe { &_USR_STACK as *const _ };
-    println!(
-        " - Loaded GDT: {:p} TSS: {:p} Stack {:p} User stack: {:p} CS segment: {} TSS segment: {}",
-        &GDT.0 as *const _, &*TSS as *const _, stack, user_stack, GDT.1[0].0, GDT.1[1].0
+
+    let selectors = GDT.1;
+    unsafe {
+        CS::set_reg(selectors.kernel_code);
+        SS::set_reg(selectors.kernel_data);
+        DS::set_reg(selectors.kernel_data);
+        ES::set_reg(selectors.kernel_data);
+        load_tss(selectors.tss);
+    }
+    sprint!(
+        " - Loaded GDT: {:p} CS: {:#x} SS: {:#x} TSS: {:#x}\n",
+        &GDT.0 as *const _,
+        selectors.kernel_code.0,
+        selectors.kernel_data.0,
+        selectors.tss.0
     );
+}
+
+/// Loads A GDT & TSS Of Its Own On An Application Processor, With Separate
+/// Interrupt Stacks, Returning The TSS. The Selectors Match The Boot CPU's,
+/// So Code Using [`selectors`] Works On Every CPU. Both Tables Are Leaked,
+/// CPUs Never Go Offline.
+pub fn init_ap() -> *mut TaskStateSegment {
+    let tss = Box::leak(Box::new(TaskStateSegment::new()));
+    tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = leak_stack();
+    tss.interrupt_stack_table[NMI_IST_INDEX as usize] = leak_stack();
+    tss.privilege_stack_table[0] = leak_stack();
+    // RSP0 Is Rewritten Through The Pointer As Threads Are Switched.
+    let tss: *mut TaskStateSegment = tss;
+
+    let gdt = Box::leak(Box::new(GlobalDescriptorTable::new()));
+    let kernel_code = gdt.add_entry(Descriptor::kernel_code_segment());
+    let kernel_data = gdt.add_entry(Descriptor::kernel_data_segment());
+    gdt.add_entry(Descriptor::user_data_segment());
+    gdt.add_entry(Descriptor::user_code_segment());
+    let tss_selector = gdt.add_entry(Descriptor::tss_segment(unsafe { &*tss }));
+    gdt.load();
 
-    sprint!("Setting Segment Selectors\n");
     unsafe {
-        CS::set_reg(GDT.1[0]);
-        CS::set_reg(GDT.1[1]);
-        load_tss(GDT.1[2]);
+        CS::set_reg(kernel_code);
+        SS::set_reg(kernel_data);
+        DS::set_reg(kernel_data);
+        ES::set_reg(kernel_data);
+        load_tss(tss_selector);
+    }
+    tss
+}
+
+/// The Boot CPU's TSS.
+pub fn tss() -> *mut TaskStateSegment {
+    core::ptr::addr_of_mut!(TSS)
+}
+
+pub fn selectors() -> Selectors {
+    GDT.1
+}
+
+/// Sets The Stack The Calling CPU Switches To When An Interrupt Arrives In
+/// Ring 3.
+pub fn set_kernel_stack(top: VirtAddr) {
+    match smp::current() {
+        Some(cpu) => cpu.set_kernel_stack(top),
+        None => unsafe { TSS.privilege_stack_table[0] = top },
     }
 }
@@ -0,0 +1,84 @@
+//! High Precision Event Timer.
+//!
+//! Only The Main Counter Is Used, As A Time Source For `clock`. The
+//! Comparators Are Left Alone & The Counter Is Enabled If The Firmware
+//! Didn't Already.
+use conquer_once::spin::OnceCell;
+use x86_64::VirtAddr;
+
+use crate::{klog, mem::dma};
+
+const CAPABILITIES: u64 = 0x00;
+const CONFIGURATION: u64 = 0x10;
+const MAIN_COUNTER: u64 = 0xF0;
+
+/// The Main Counter Is 64 Bits Wide.
+const CAP_COUNTER_64: u64 = 1 << 13;
+const CONFIG_ENABLE: u64 = 1 << 0;
+
+static HPET: OnceCell<Hpet> = OnceCell::uninit();
+
+pub struct Hpet {
+    base: VirtAddr,
+    /// Femtoseconds Per Counter Tick.
+    period: u64,
+    wide: bool,
+}
+
+impl Hpet {
+    fn new(base: VirtAddr) -> Self {
+        let mut hpet = Self {
+            base,
+            period: 0,
+            wide: false,
+        };
+        let capabilities = hpet.read(CAPABILITIES);
+        hpet.period = capabilities >> 32;
+        hpet.wide = capabilities & CAP_COUNTER_64 != 0;
+
+        let config = hpet.read(CONFIGURATION);
+        if config & CONFIG_ENABLE == 0 {
+            hpet.write(CONFIGURATION, config | CONFIG_ENABLE);
+        }
+        hpet
+    }
+
+    fn read(&self, register: u64) -> u64 {
+        unsafe { (self.base + register).as_ptr::<u64>().read_volatile() }
+    }
+
+    fn write(&self, register: u64, value: u64) {
+        unsafe { (self.base + register).as_mut_ptr::<u64>().write_volatile(value) }
+    }
+
+    /// Femtoseconds Per Tick Of The Main Counter.
+    pub fn period(&self) -> u64 {
+        self.period
+    }
+
+    /// Whether The Main Counter Is 64 Bits, A 32 Bit One Wraps Every Few
+    /// Minutes.
+    pub fn is_wide(&self) -> bool {
+        self.wide
+    }
+
+    pub fn counter(&self) -> u64 {
+        self.read(MAIN_COUNTER)
+    }
+}
+
+/// Finds & Enables The HPET Described By The ACPI Tables. Needs The Heap.
+pub fn initialize() -> Option<&'static Hpet> {
+    let phys = super::acpi::hpet_address()?;
+    let hpet = HPET.get_or_init(|| Hpet::new(dma::map_mmio(phys, 0x400)));
+    klog!(
+        "HPET: {} Bit Counter At {} kHz\n",
+        if hpet.wide { 64 } else { 32 },
+        1_000_000_000_000 / hpet.period.max(1)
+    );
+    Some(hpet)
+}
+
+pub fn get() -> Option<&'static Hpet> {
+    HPET.get()
+}
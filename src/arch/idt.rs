@@ -1,11 +1,21 @@
-use crate::arch::pic;
+use alloc::boxed::Box;
+use crate::arch::{apic, irq};
 use crate::input::wait_for_key;
-use crate::{sprint, time};
+use crate::mem::{
+    self, address_space,
+    regions::{self, Fault},
+};
+use crate::task::wake::{self, WakeSource};
+use crate::{klog, process, sprint, time};
 use lazy_static::lazy_static;
 use x86_64::registers::control::Cr2;
 use x86_64::structures::idt::{InterruptStackFrame, PageFaultErrorCode};
+use x86_64::{PrivilegeLevel, VirtAddr};
 
 use super::cmos::CMOS;
+use super::context::{self, Context};
+use super::smp::{self, KernelGs};
+use super::gdt;
 use super::pic::*;
 use super::x64::structures::idt::InterruptDescriptorTable;
 
@@ -22,45 +32,108 @@ pub enum Interrupts {
     AtaB1,
 }
 
+/// Software Interrupt Used By `task::yield_now` To Enter The Scheduler.
+pub const YIELD_VECTOR: u8 = 0x81;
+
+/// Software Interrupt Used By Programs To Make System Calls.
+pub const SYSCALL_VECTOR: u8 = 0x80;
+
+/// Raised By The Local APIC Timer Of Each AP To Enter The Scheduler.
+pub const LOCAL_TIMER_VECTOR: u8 = 0x82;
+
 impl Interrupts {
     pub fn as_u8(&self) -> u8 {
         *self as u8
     }
 
+    /// The ISA IRQ Line Behind The Interrupt.
+    pub fn irq(&self) -> IrqIndex {
+        self.as_u8() - PIC1
+    }
+
     pub fn as_usize(&self) -> usize {
         *self as usize
     }
 }
 
 lazy_static! {
-    static ref IDT: InterruptDescriptorTable = {
-        let mut idt = InterruptDescriptorTable::new();
+    static ref IDT: InterruptDescriptorTable = build();
+}
 
-        idt.breakpoint.set_handler_fn(breakpoint);
-        idt.double_fault.set_handler_fn(double_fault);
-        idt.page_fault.set_handler_fn(page_fault);
-        idt.divide_error.set_handler_fn(divide_err);
-        idt.general_protection_fault.set_handler_fn(gen_protection);
+/// Every CPU Gets A Table Of Its Own, All With The Same Entries.
+fn build() -> InterruptDescriptorTable {
+    let mut idt = InterruptDescriptorTable::new();
 
-        idt[Interrupts::Timer.as_usize()].set_handler_fn(timer);
-        idt[Interrupts::Keyboard.as_usize()].set_handler_fn(keyboard);
+    idt.breakpoint.set_handler_fn(breakpoint);
+    unsafe {
+        idt.non_maskable_interrupt
+            .set_handler_fn(nmi)
+            .set_stack_index(gdt::NMI_IST_INDEX);
+        idt.double_fault
+            .set_handler_fn(double_fault)
+            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+    }
+    // Runs On The Faulting Thread's Kernel Stack, Not An IST Stack, As
+    // Filling The Page Can Block On Disk I/O & Switch Threads.
+    idt.page_fault.set_handler_fn(page_fault);
+    idt.divide_error.set_handler_fn(divide_err);
+    idt.invalid_opcode.set_handler_fn(invalid_opcode);
+    idt.segment_not_present.set_handler_fn(segment_not_present);
+    idt.stack_segment_fault.set_handler_fn(stack_segment);
+    idt.general_protection_fault.set_handler_fn(gen_protection);
 
-        idt[Interrupts::Cmos.as_usize()].set_handler_fn(cmos_nmi);
+    unsafe {
+        idt[Interrupts::Timer.as_usize()].set_handler_addr(VirtAddr::new(context::timer_entry as *const () as u64));
+        idt[LOCAL_TIMER_VECTOR as usize].set_handler_addr(VirtAddr::new(context::local_timer_entry as *const () as u64));
+        idt[YIELD_VECTOR as usize].set_handler_addr(VirtAddr::new(context::yield_entry as *const () as u64));
+        idt[SYSCALL_VECTOR as usize]
+            .set_handler_addr(VirtAddr::new(context::syscall_entry as *const () as u64))
+            .set_privilege_level(PrivilegeLevel::Ring3);
+    }
+    for (irq, stub) in irq::STUBS.iter().enumerate().skip(1) {
+        idt[PIC1 as usize + irq].set_handler_fn(*stub);
+    }
 
-        idt[Interrupts::AtaB0.as_usize()].set_handler_fn(ata0);
-        idt[Interrupts::AtaB1.as_usize()].set_handler_fn(ata1);
+    idt[apic::SPURIOUS_VECTOR as usize].set_handler_fn(spurious);
 
-        idt
-    };
+    idt
 }
 
 pub fn initialize() {
-    // FIXME(george): Triple Faults When Initializing the GDT.
-    //super::gdt::init_gdt();
+    gdt::init();
     IDT.load();
 }
 
+/// Builds & Loads An IDT Of Its Own On An Application Processor. It Is
+/// Leaked, CPUs Never Go Offline.
+pub fn init_ap() {
+    Box::leak(Box::new(build())).load();
+}
+
+/// Registers The Handlers For The Devices The Kernel Always Drives.
+pub fn register_handlers() {
+    let handlers: [(Interrupts, irq::Handler); 5] = [
+        (Interrupts::Timer, timer),
+        (Interrupts::Keyboard, keyboard),
+        (Interrupts::Cmos, cmos_nmi),
+        (Interrupts::AtaB0, ata),
+        (Interrupts::AtaB1, ata),
+    ];
+    for (interrupt, handler) in handlers {
+        irq::register(interrupt.irq(), handler).expect("Failed To Register IRQ Handler");
+    }
+}
+
+extern "x86-interrupt" fn spurious(_: InterruptStackFrame) {}
+
+extern "x86-interrupt" fn nmi(_: InterruptStackFrame) {
+    if !smp::handle_nmi() {
+        sprint!("NMI\n");
+    }
+}
+
 extern "x86-interrupt" fn breakpoint(frame: InterruptStackFrame) {
+    let _gs = KernelGs::enter(&frame);
     sprint!("#BP @ ${:08x}\n", frame.instruction_pointer.as_u64());
     wait_for_key()
 }
@@ -69,47 +142,138 @@ extern "x86-interrupt" fn double_fault(frame: InterruptStackFrame, _: u64) -> !
     panic!("#DF - RIP: V${:08x}\n", frame.instruction_pointer.as_u64())
 }
 
-extern "x86-interrupt" fn page_fault(_: InterruptStackFrame, ec: PageFaultErrorCode) {
-    if (!ec.bits() & !PageFaultErrorCode::PROTECTION_VIOLATION.bits()) == 0 {
-        crate::mem::map_virt(
-            Cr2::read(),
-            crate::mem::PTFlags::PRESENT | crate::mem::PTFlags::WRITABLE,
+extern "x86-interrupt" fn page_fault(frame: InterruptStackFrame, ec: PageFaultErrorCode) {
+    let _gs = KernelGs::enter(&frame);
+    let addr = Cr2::read();
+    let present = ec.contains(PageFaultErrorCode::PROTECTION_VIOLATION);
+    let write = ec.contains(PageFaultErrorCode::CAUSED_BY_WRITE);
+    let rip = frame.instruction_pointer.as_u64();
+
+    if ec.contains(PageFaultErrorCode::USER_MODE) {
+        let result = process::resolve_fault(addr, present, write);
+        match result {
+            Some(Ok(())) => return,
+            Some(Err(fault)) => {
+                klog!(
+                    "Segmentation Fault ({:?}) In Process {} - CR2: {:#x} Error: {:?} RIP: {:#x}\n",
+                    fault,
+                    process::current(),
+                    addr,
+                    ec,
+                    rip
+                );
+                process::exit(process::SEGFAULT);
+            }
+            None => {}
+        }
+    } else if !present {
+        // Mapped By The Kernel After The Active Address Space Was Loaded, In
+        // Any Kernel Region, Not Only The Heap.
+        if address_space::is_kernel_mapped(addr) {
+            address_space::sync_active();
+            if mem::page_flags(addr).is_some() {
+                return;
+            }
+        } else if let Fault::Map(flags) = regions::classify_kernel(addr.as_u64()) {
+            mem::map_fresh(addr, flags);
+            return;
+        }
+    }
+
+    panic!(
+        "#PF - CR2: {:#x} Error: {:?} RIP: {:#x}",
+        addr.as_u64(),
+        ec,
+        rip
+    )
+}
+
+/// Kills The Current Process With `code` If `frame` Was Pushed In Ring 3,
+/// Returning Only For Faults Raised By The Kernel Itself.
+fn kill_user(frame: &InterruptStackFrame, fault: &str, code: u8) {
+    if frame.code_segment & 3 == 3 {
+        klog!(
+            "{} In Process {} - RIP: {:#x}\n",
+            fault,
+            process::current(),
+            frame.instruction_pointer.as_u64()
         );
+        process::exit(code);
     }
 }
 
-extern "x86-interrupt" fn gen_protection(_: InterruptStackFrame, ec: u64) {
-    sprint!("#GP - General Protection Fault {:#016X}...\n", ec);
+extern "x86-interrupt" fn gen_protection(frame: InterruptStackFrame, ec: u64) {
+    let _gs = KernelGs::enter(&frame);
+    kill_user(&frame, "General Protection Fault", process::SEGFAULT);
+    panic!("#GP - Error: {:#x} RIP: {:#x}", ec, frame.instruction_pointer.as_u64())
+}
+
+extern "x86-interrupt" fn segment_not_present(frame: InterruptStackFrame, ec: u64) {
+    let _gs = KernelGs::enter(&frame);
+    kill_user(&frame, "Segment Not Present", process::SEGFAULT);
+    panic!("#NP - Error: {:#x} RIP: {:#x}", ec, frame.instruction_pointer.as_u64())
+}
 
-    loop {}
+extern "x86-interrupt" fn stack_segment(frame: InterruptStackFrame, ec: u64) {
+    let _gs = KernelGs::enter(&frame);
+    kill_user(&frame, "Stack Segment Fault", process::SEGFAULT);
+    panic!("#SS - Error: {:#x} RIP: {:#x}", ec, frame.instruction_pointer.as_u64())
 }
 
-extern "x86-interrupt" fn divide_err(sf: InterruptStackFrame) {
-    sprint!("#DE - ${:016x}\n", sf.instruction_pointer);
+extern "x86-interrupt" fn divide_err(frame: InterruptStackFrame) {
+    let _gs = KernelGs::enter(&frame);
+    kill_user(&frame, "Divide Error", process::ARITHMETIC_FAULT);
+    panic!("#DE - RIP: {:#x}", frame.instruction_pointer.as_u64())
 }
 
-extern "x86-interrupt" fn timer(_: InterruptStackFrame) {
+extern "x86-interrupt" fn invalid_opcode(frame: InterruptStackFrame) {
+    let _gs = KernelGs::enter(&frame);
+    kill_user(&frame, "Invalid Opcode", process::ILLEGAL_INSTRUCTION);
+    panic!("#UD - RIP: {:#x}", frame.instruction_pointer.as_u64())
+}
+
+#[no_mangle]
+extern "C" fn timer_switch(context: *mut Context) -> *mut Context {
     //crate::sprint!("Tick!\n");
+    irq::handle(Interrupts::Timer.irq());
+    irq::eoi(Interrupts::Timer.irq());
+    crate::task::scheduler::tick(context)
+}
+
+#[no_mangle]
+extern "C" fn local_timer_switch(context: *mut Context) -> *mut Context {
+    apic::eoi();
+    crate::task::scheduler::tick(context)
+}
+
+#[no_mangle]
+extern "C" fn yield_switch(context: *mut Context) -> *mut Context {
+    crate::task::scheduler::switch(context)
+}
+
+#[no_mangle]
+extern "C" fn syscall_switch(context: *mut Context) -> *mut Context {
+    crate::syscall::dispatch(unsafe { &mut *context });
+    context
+}
+
+fn timer() {
     crate::pit::update_timers();
     crate::graphics_2d::vblank();
-    pic::notify_eoi(Interrupts::Timer.as_u8())
+    wake::wake(WakeSource::Timer);
 }
 
-extern "x86-interrupt" fn keyboard(_: InterruptStackFrame) {
+fn keyboard() {
     crate::input::keyboard::keypress();
-    pic::notify_eoi(Interrupts::Keyboard.as_u8());
-}
-
-extern "x86-interrupt" fn ata0(_: InterruptStackFrame) {
-    pic::notify_eoi(Interrupts::AtaB0.as_u8());
+    wake::wake(WakeSource::Keyboard);
 }
 
-extern "x86-interrupt" fn ata1(_: InterruptStackFrame) {
-    pic::notify_eoi(Interrupts::AtaB1.as_u8());
+fn ata() {
+    wake::wake(WakeSource::Ata);
 }
 
-extern "x86-interrupt" fn cmos_nmi(_: InterruptStackFrame) {
+fn cmos_nmi() {
     time::rtc_tick();
+    wake::wake(WakeSource::Cmos);
     CMOS::new().notify_end_of_interrupt();
-    pic::notify_eoi(Interrupts::Cmos.as_u8());
 }
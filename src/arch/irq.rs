@@ -0,0 +1,213 @@
+//! Hardware IRQ Handler Registration.
+//!
+//! Every ISA Line Has Its Own Vector (`PIC1 + IRQ`) Whose Stub Counts The
+//! Interrupt, Runs The Handlers Registered For The Line & Sends The EOI.
+//! Lines Can Be Shared, So A Handler Must Check Its Device Actually Raised
+//! The Interrupt. A Line Is Unmasked While It Has At Least One Handler.
+//! The Table Is Fixed Size So Handlers Can Be Registered Before The Heap.
+use alloc::string::String;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::{
+    instructions::interrupts::without_interrupts,
+    structures::idt::{HandlerFunc, InterruptStackFrame},
+};
+
+use crate::{
+    csh::{ExitCode, ShellArgs},
+    println,
+};
+
+use super::{
+    apic,
+    pic::{self, IrqIndex, PIC1},
+    smp::KernelGs,
+};
+
+pub const IRQ_COUNT: usize = 16;
+
+/// Most Handlers That Can Share One Line.
+pub const MAX_SHARED: usize = 4;
+
+pub type Handler = fn();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// Not An ISA IRQ.
+    InvalidIrq,
+    /// The Line Already Has `MAX_SHARED` Handlers.
+    LineFull,
+    /// The Handler Was Already Unregistered.
+    NotRegistered,
+}
+
+/// Identifies A Registered Handler, For `unregister`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandlerId {
+    irq: IrqIndex,
+    id: u32,
+}
+
+impl HandlerId {
+    pub fn irq(&self) -> IrqIndex {
+        self.irq
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+    id: u32,
+    handler: Handler,
+}
+
+type Line = [Option<Slot>; MAX_SHARED];
+
+static HANDLERS: Mutex<[Line; IRQ_COUNT]> = Mutex::new([[None; MAX_SHARED]; IRQ_COUNT]);
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+static COUNTS: [AtomicU64; IRQ_COUNT] = [const { AtomicU64::new(0) }; IRQ_COUNT];
+
+/// The Entry Stub For Each Line, Installed At `PIC1 + IRQ`. The Timer's
+/// Vector Instead Enters The Scheduler, Which Calls [`handle`] Itself.
+pub(super) const STUBS: [HandlerFunc; IRQ_COUNT] = [
+    stub::<0>,
+    stub::<1>,
+    stub::<2>,
+    stub::<3>,
+    stub::<4>,
+    stub::<5>,
+    stub::<6>,
+    stub::<7>,
+    stub::<8>,
+    stub::<9>,
+    stub::<10>,
+    stub::<11>,
+    stub::<12>,
+    stub::<13>,
+    stub::<14>,
+    stub::<15>,
+];
+
+extern "x86-interrupt" fn stub<const IRQ: IrqIndex>(frame: InterruptStackFrame) {
+    let _gs = KernelGs::enter(&frame);
+    handle(IRQ);
+    eoi(IRQ);
+}
+
+/// Calls `handler` Whenever `irq` Is Raised, Alongside Any Other Handlers
+/// On The Line.
+pub fn register(irq: IrqIndex, handler: Handler) -> Result<HandlerId, IrqError> {
+    if irq as usize >= IRQ_COUNT {
+        return Err(IrqError::InvalidIrq);
+    }
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        let line = &mut handlers[irq as usize];
+        let free = line.iter_mut().find(|slot| slot.is_none()).ok_or(IrqError::LineFull)?;
+        *free = Some(Slot { id, handler });
+        set_masked(irq, false);
+        Ok(HandlerId { irq, id })
+    })
+}
+
+/// Removes A Handler, Masking The Line If It Was The Last One.
+pub fn unregister(id: HandlerId) -> Result<(), IrqError> {
+    without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        let line = handlers
+            .get_mut(id.irq as usize)
+            .ok_or(IrqError::InvalidIrq)?;
+        let slot = line
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|slot| slot.id == id.id))
+            .ok_or(IrqError::NotRegistered)?;
+        *slot = None;
+
+        if line.iter().all(Option::is_none) {
+            set_masked(id.irq, true);
+        }
+        Ok(())
+    })
+}
+
+/// Number Of Handlers Registered For `irq`.
+pub fn handler_count(irq: IrqIndex) -> usize {
+    without_interrupts(|| match HANDLERS.lock().get(irq as usize) {
+        Some(line) => line.iter().flatten().count(),
+        None => 0,
+    })
+}
+
+/// Number Of Times `irq` Has Been Raised.
+pub fn count(irq: IrqIndex) -> u64 {
+    COUNTS
+        .get(irq as usize)
+        .map_or(0, |count| count.load(Ordering::Relaxed))
+}
+
+/// Counts An Interrupt On `irq` & Runs Its Handlers. Called With Interrupts
+/// Disabled, Before The EOI.
+pub(super) fn handle(irq: IrqIndex) {
+    COUNTS[irq as usize].fetch_add(1, Ordering::Relaxed);
+
+    // Copied Out So Handlers Can Register & Unregister.
+    let line = HANDLERS.lock()[irq as usize];
+    for slot in line.iter().flatten() {
+        (slot.handler)();
+    }
+}
+
+/// Acknowledges `irq` With Whichever Controller Delivered It.
+pub(super) fn eoi(irq: IrqIndex) {
+    if apic::is_enabled() {
+        apic::eoi();
+    } else {
+        pic::notify_eoi(PIC1 + irq);
+    }
+}
+
+fn set_masked(irq: IrqIndex, masked: bool) {
+    if apic::is_enabled() {
+        if let Some(gsi) = apic::isa_gsi(irq) {
+            apic::set_masked(gsi, masked);
+        }
+    } else {
+        pic::set_masked(irq, masked);
+    }
+}
+
+/// Masks Every Line Without Handlers & Unmasks The Rest, For When The
+/// Interrupt Controller Changes.
+pub(super) fn sync_masks() {
+    for irq in 0..IRQ_COUNT as IrqIndex {
+        set_masked(irq, handler_count(irq) == 0);
+    }
+}
+
+/// Prints How Often Each Line Has Fired & How Many Handlers It Has.
+pub fn csh_irqstat(_: ShellArgs) -> ExitCode {
+    println!("IRQ  Vector  Route    Handlers  Count");
+    for irq in 0..IRQ_COUNT as IrqIndex {
+        let handlers = handler_count(irq);
+        let count = count(irq);
+        if handlers == 0 && count == 0 {
+            continue;
+        }
+
+        let route = match (apic::is_enabled(), apic::isa_gsi(irq)) {
+            (true, Some(gsi)) => alloc::format!("GSI {}", gsi),
+            (true, None) => String::from("None"),
+            (false, _) => String::from("8259"),
+        };
+        println!(
+            "{:>3}  {:#6x}  {:<7}  {:>8}  {}",
+            irq,
+            PIC1 + irq,
+            route,
+            handlers,
+            count
+        );
+    }
+    ExitCode::Ok
+}
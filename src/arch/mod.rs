@@ -1,22 +1,80 @@
 pub use x86_64 as x64;
 
-use x64::instructions::port::Port;
+use x64::{
+    instructions::{port::Port, tables::lidt},
+    structures::DescriptorTablePointer,
+    VirtAddr,
+};
 
-use crate::kprog;
+use crate::{
+    input::ps2::{self, PS2Controller},
+    kprog, pit,
+};
 
 pub mod acpi;
+pub mod apic;
 pub mod cmos;
+pub mod context;
 pub mod cpu;
-mod gdt;
+pub mod gdt;
+pub mod hpet;
 mod idt;
+pub mod irq;
 mod pic;
+pub mod smp;
+pub mod syscall;
 pub mod vmm;
 
 pub mod io;
 
 pub fn initialize_interrupts() {
     idt::initialize();
+    syscall::init();
     pic::initialize();
+    idt::register_handlers();
+}
+
+/// Moves Hardware Interrupts From The 8259 To The APIC, If There Is One.
+/// Needs The Heap & The PIT Running.
+pub fn initialize_apic() {
+    if apic::initialize() {
+        irq::sync_masks();
+    }
+}
+
+/// Milliseconds Each Reset Method Gets Before The Next Is Tried.
+const RESET_WAIT: usize = 100;
+
+/// Resets The Machine Through The ACPI Reset Register, Falling Back To The
+/// 8042 & Then A Triple Fault.
+pub fn reset() -> ! {
+    kprog!("Resetting Through ACPI");
+    acpi::reset();
+    pit::sleep(RESET_WAIT);
+
+    kprog!("Resetting Through The 8042");
+    let _ = PS2Controller::get().command(ps2::Command::PulseReset);
+    pit::sleep(RESET_WAIT);
+
+    kprog!("Resetting With A Triple Fault");
+    triple_fault()
+}
+
+/// Loads An Empty IDT & Raises An Exception, Which Can't Be Delivered.
+fn triple_fault() -> ! {
+    x64::instructions::interrupts::disable();
+    let idt = DescriptorTablePointer {
+        limit: 0,
+        base: VirtAddr::zero(),
+    };
+    unsafe {
+        lidt(&idt);
+    }
+    x64::instructions::interrupts::int3();
+
+    loop {
+        x64::instructions::hlt();
+    }
 }
 
 pub fn spin() {
@@ -9,11 +9,33 @@ pub type IrqIndex = u8;
 pub const PIC1: IrqIndex = 0x20;
 pub const PIC2: IrqIndex = PIC1 + 8;
 
+/// The Line The Secondary PIC Is Chained To.
+const CASCADE_IRQ: IrqIndex = 2;
+
+/// Remaps The PICs & Masks Every Line But The Cascade, Lines Are Unmasked
+/// As Handlers Are Registered With `irq::register`.
 pub fn initialize() {
     PICS.init_once(|| Locked::new(unsafe { ChainedPics::new(PIC1, PIC2) }));
 
     unsafe {
-        PICS.get().unwrap().lock().initialize();
+        let mut pics = PICS.get().unwrap().lock();
+        pics.initialize();
+        pics.write_masks(!(1 << CASCADE_IRQ), 0xFF);
+    }
+}
+
+/// Masks Or Unmasks ISA `irq`.
+pub fn set_masked(irq: IrqIndex, masked: bool) {
+    if let Some(pics) = PICS.get() {
+        let mut pics = pics.lock();
+        let mut masks = unsafe { pics.read_masks() };
+        let (chip, line) = ((irq / 8) as usize, irq % 8);
+        if masked {
+            masks[chip] |= 1 << line;
+        } else {
+            masks[chip] &= !(1 << line);
+        }
+        unsafe { pics.write_masks(masks[0], masks[1]) }
     }
 }
 
@@ -24,3 +46,13 @@ pub fn notify_eoi(irq: IrqIndex) {
         }
     }
 }
+
+/// Masks Every Line, For When The APIC Takes Over. The PICs Stay Remapped So
+/// A Spurious IRQ Can't Land On An Exception Vector.
+pub fn disable() {
+    if let Some(pics) = PICS.get() {
+        unsafe {
+            pics.lock().disable();
+        }
+    }
+}
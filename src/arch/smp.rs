@@ -0,0 +1,531 @@
+//! Starting The Other CPUs.
+//!
+//! Each Application Processor Listed In The MADT Is Woken With INIT-SIPI-SIPI
+//! Into A Real Mode Trampoline Copied Below 1 MiB, Which Climbs Through
+//! Protected Mode Into Long Mode On The Kernel's Page Tables & Calls
+//! `ap_entry` On A Fresh Stack. APs Are Started One At A Time As They Share
+//! The Trampoline. Once Running, An AP Loads Its Own GDT, TSS & IDT, Enables
+//! Its Local APIC & Waits For The Scheduler, Then Joins It With An Idle
+//! Thread Of Its Own & A Local APIC Timer Driving Preemption.
+//!
+//! Every CPU Finds Its [`Cpu`] Block Through The GS Base. Both GS Base MSRs
+//! Point At The Block & The Entries From Ring 3 That Reach Per-CPU Data
+//! (`syscall`, The Switch Stubs, IRQs & Page Faults) `swapgs` In & Out, So
+//! The Kernel Keeps Its Block Even If A Program Reloads GS.
+//!
+//! Page Table Changes Other CPUs May Have Cached Are Flushed By
+//! [`shootdown`], Which Uses NMIs So It Can't Deadlock Against A CPU Spinning
+//! On A Lock With Interrupts Off.
+use core::{
+    arch::{asm, global_asm},
+    mem::offset_of,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering},
+};
+
+use alloc::{boxed::Box, vec, vec::Vec};
+use spin::Mutex;
+use x86_64::{
+    instructions::{
+        interrupts,
+        segmentation::GS,
+        tlb,
+    },
+    registers::{
+        control::{Cr0, Cr4, Cr4Flags},
+        model_specific::{Efer, GsBase, KernelGsBase},
+    },
+    structures::{idt::InterruptStackFrame, paging::PhysFrame, tss::TaskStateSegment},
+    PhysAddr, VirtAddr,
+};
+
+use crate::{
+    csh::{ExitCode, ShellArgs},
+    klog,
+    mem::{
+        self,
+        frames::{self, Zone},
+        PTFlags,
+    },
+    pit, println, task,
+};
+
+use super::{apic, cpu, gdt, idt, syscall};
+
+/// Most CPUs Brought Online, Including The Boot CPU.
+pub const MAX_CPUS: usize = 16;
+
+const AP_STACK_SIZE: usize = 4096 * 16;
+
+/// Period Of Each AP's Local APIC Timer, Matching The PIT On The Boot CPU.
+const AP_TIMER_MS: u32 = 1;
+
+/// Offsets Into [`Cpu`] Used By The `syscall` Entry.
+pub(super) const CPU_KERNEL_RSP: usize = offset_of!(Cpu, kernel_rsp);
+pub(super) const CPU_USER_RSP: usize = offset_of!(Cpu, user_rsp);
+pub(super) const CPU_USER_CS: usize = offset_of!(Cpu, user_cs);
+pub(super) const CPU_USER_SS: usize = offset_of!(Cpu, user_ss);
+
+/// How Long An AP Gets To Report In After Its Startup IPIs.
+const AP_TIMEOUT_MS: u64 = 100;
+
+/// EFER.LMA Is Set By The CPU & Can't Be Written.
+const EFER_LMA: u64 = 1 << 10;
+
+/// Every CPU That Has Come Online, In Order.
+static ONLINE: Mutex<Vec<&'static Cpu>> = Mutex::new(Vec::new());
+/// Set By An AP Once It No Longer Needs The Trampoline.
+static AP_READY: AtomicBool = AtomicBool::new(false);
+/// CR4 Of The Boot CPU, Copied By Each AP Once In Long Mode.
+static BSP_CR4: AtomicU64 = AtomicU64::new(0);
+
+/// TLB Flushes Asked Of & Done By Each CPU, Indexed By Local APIC ID. Read
+/// From The NMI Handler, Which Can't Trust GS.
+static FLUSHES_REQUESTED: [AtomicU64; 256] = [const { AtomicU64::new(0) }; 256];
+static FLUSHES_DONE: [AtomicU64; 256] = [const { AtomicU64::new(0) }; 256];
+
+/// Data Belonging To One CPU, Reached Through GS.
+#[repr(C)]
+pub struct Cpu {
+    /// Points At The Block Itself, So It Can Be Read From `gs:[0]`.
+    this: u64,
+    /// The Running Thread's Kernel Stack, Kept In Step With The TSS's RSP0.
+    kernel_rsp: AtomicU64,
+    /// Scratch Space For The User Stack Pointer While `syscall` Switches
+    /// Stacks.
+    user_rsp: AtomicU64,
+    user_cs: u64,
+    user_ss: u64,
+    tss: AtomicPtr<TaskStateSegment>,
+    /// Position In The Order CPUs Came Online, The Boot CPU Is 0.
+    pub index: usize,
+    pub apic_id: u8,
+    pub topology: cpu::Topology,
+}
+
+/// Written Into The Trampoline For The AP Being Started, Must Match The
+/// Layout At `ap_trampoline_data`.
+#[repr(C)]
+struct TrampolineData {
+    cr3: u64,
+    cr0: u64,
+    stack: u64,
+    cpu: u64,
+    entry: u64,
+    efer: u64,
+}
+
+global_asm!(
+    ".pushsection .text.ap_trampoline, \"ax\"",
+    ".global ap_trampoline",
+    ".global ap_trampoline_end",
+    ".global ap_gdt",
+    ".global ap_gdtr",
+    ".global ap_protected_jump",
+    ".global ap_protected",
+    ".global ap_long_jump",
+    ".global ap_long",
+    ".global ap_trampoline_data",
+    ".code16",
+    "ap_trampoline:",
+    "    jmp ap_real",
+    // Data Comes First So Its Offsets Are Known Where The Code Uses Them.
+    ".align 8",
+    "ap_trampoline_data:",
+    "    .fill 6, 8, 0",
+    "ap_gdt:",
+    "    .quad 0",
+    "    .quad 0x00CF9A000000FFFF",
+    "    .quad 0x00CF92000000FFFF",
+    "    .quad 0x00AF9A000000FFFF",
+    "ap_gdtr:",
+    "    .word ap_gdtr - ap_gdt - 1",
+    "    .long 0",
+    ".set AP_DATA, ap_trampoline_data - ap_trampoline",
+    ".set AP_GDTR, ap_gdtr - ap_trampoline",
+    "ap_real:",
+    "    cli",
+    "    cld",
+    "    mov ax, cs",
+    "    mov ds, ax",
+    // EBX Holds The Trampoline's Physical Address From Here On.
+    "    xor ebx, ebx",
+    "    mov bx, ax",
+    "    shl ebx, 4",
+    "    lgdt [AP_GDTR]",
+    "    mov eax, cr0",
+    "    or eax, 1",
+    "    mov cr0, eax",
+    // `jmp 0x08:ap_protected`, The Target Is Patched In As It Depends On
+    // Where The Trampoline Was Copied.
+    "    .byte 0x66, 0xEA",
+    "ap_protected_jump:",
+    "    .long 0",
+    "    .word 0x08",
+    ".code32",
+    "ap_protected:",
+    "    mov ax, 0x10",
+    "    mov ds, ax",
+    "    mov es, ax",
+    "    mov ss, ax",
+    "    mov eax, cr4",
+    "    or eax, 1 << 5",
+    "    mov cr4, eax",
+    "    mov eax, [ebx + AP_DATA]",
+    "    mov cr3, eax",
+    "    mov ecx, 0xC0000080",
+    "    mov eax, [ebx + AP_DATA + 40]",
+    "    xor edx, edx",
+    "    wrmsr",
+    "    mov eax, [ebx + AP_DATA + 8]",
+    "    mov cr0, eax",
+    // `jmp 0x18:ap_long`, Patched Like The First Jump.
+    "    .byte 0xEA",
+    "ap_long_jump:",
+    "    .long 0",
+    "    .word 0x18",
+    ".code64",
+    "ap_long:",
+    "    xor eax, eax",
+    "    mov ds, ax",
+    "    mov es, ax",
+    "    mov ss, ax",
+    "    mov ebx, ebx",
+    "    mov rsp, [rbx + AP_DATA + 16]",
+    "    mov rdi, [rbx + AP_DATA + 24]",
+    "    mov rax, [rbx + AP_DATA + 32]",
+    "    and rsp, -16",
+    "    call rax",
+    "    ud2",
+    "ap_trampoline_end:",
+    ".popsection",
+);
+
+extern "C" {
+    fn ap_trampoline();
+    fn ap_trampoline_end();
+    fn ap_gdtr();
+    fn ap_gdt();
+    fn ap_protected_jump();
+    fn ap_protected();
+    fn ap_long_jump();
+    fn ap_long();
+    fn ap_trampoline_data();
+}
+
+/// Offset Of A Trampoline Label From Its Start.
+fn offset(label: unsafe extern "C" fn()) -> u64 {
+    label as *const () as u64 - ap_trampoline as *const () as u64
+}
+
+/// Sets Up The Boot CPU's Block & Starts Every AP In The MADT, Returning
+/// How Many CPUs Are Online. Needs The APIC & The PIT Running.
+pub fn init() -> usize {
+    publish(Box::leak(Box::new(Cpu::new(0))), gdt::tss());
+    BSP_CR4.store(Cr4::read_raw(), Ordering::Relaxed);
+
+    let processors = apic::application_processors();
+    if processors.len() >= MAX_CPUS {
+        klog!("SMP: Only Starting {} Of {} CPUs\n", MAX_CPUS, processors.len() + 1);
+    }
+    if !processors.is_empty() {
+        match Trampoline::new() {
+            Some(trampoline) => {
+                for apic_id in processors.iter().copied().take(MAX_CPUS - 1) {
+                    if !trampoline.start(apic_id) {
+                        klog!("SMP: CPU With APIC ID {} Didn't Start\n", apic_id);
+                    }
+                }
+            }
+            None => klog!("SMP: No Room For The Trampoline, Only Using The Boot CPU\n"),
+        }
+    }
+
+    let count = count();
+    let topology = cpu::topology();
+    klog!(
+        "SMP: {} CPU(s) Online ({} Thread(s) Per Core, {} Per Package)\n",
+        count,
+        topology.threads_per_core,
+        topology.threads_per_package
+    );
+    count
+}
+
+/// The Low Page The APs Start In, Identity Mapped While It Exists.
+struct Trampoline {
+    frame: PhysFrame,
+}
+
+impl Trampoline {
+    fn new() -> Option<Self> {
+        // The Trampoline Loads CR3 In 32-Bit Mode.
+        if mem::kernel_page_table().start_address().as_u64() >= 1 << 32 {
+            return None;
+        }
+
+        let frame = frames::allocate_in(Zone::Low)?;
+        let base = frame.start_address();
+        let virt = VirtAddr::new(base.as_u64());
+        if mem::is_mapped(virt) {
+            frames::deallocate(frame);
+            return None;
+        }
+        mem::map_virt_to_phys(virt, base, PTFlags::PRESENT | PTFlags::WRITABLE);
+
+        let len = offset(ap_trampoline_end) as usize;
+        let code = unsafe { core::slice::from_raw_parts(ap_trampoline as *const u8, len) };
+        let patch = |at: u64, target: u64| unsafe {
+            (Self::at(base, at) as *mut u32).write_unaligned((base.as_u64() + target) as u32);
+        };
+        unsafe {
+            core::ptr::copy_nonoverlapping(code.as_ptr(), Self::at(base, 0), len);
+        }
+        // The GDT Pointer's Base Follows Its 16-Bit Limit.
+        patch(offset(ap_gdtr) + 2, offset(ap_gdt));
+        patch(offset(ap_protected_jump), offset(ap_protected));
+        patch(offset(ap_long_jump), offset(ap_long));
+        Some(Self { frame })
+    }
+
+    /// Where `offset` Into The Trampoline Can Be Written.
+    fn at(base: PhysAddr, offset: u64) -> *mut u8 {
+        let virt = mem::phys_to_virt(base + offset).expect("Physical Memory Is Not Mapped");
+        virt.as_mut_ptr()
+    }
+
+    /// Starts The AP With `apic_id`, Returning `false` If It Never Reports.
+    fn start(&self, apic_id: u8) -> bool {
+        let base = self.frame.start_address();
+        let stack: &'static mut [u8] = Box::leak(vec![0; AP_STACK_SIZE].into_boxed_slice());
+        let cpu = Box::leak(Box::new(Cpu::new(apic_id)));
+
+        let data = TrampolineData {
+            cr3: mem::kernel_page_table().start_address().as_u64(),
+            cr0: Cr0::read_raw(),
+            stack: stack.as_ptr() as u64 + AP_STACK_SIZE as u64,
+            cpu: cpu as *const Cpu as u64,
+            entry: ap_entry as *const () as u64,
+            efer: Efer::read_raw() & !EFER_LMA,
+        };
+        unsafe {
+            (Self::at(base, offset(ap_trampoline_data)) as *mut TrampolineData).write(data);
+        }
+
+        AP_READY.store(false, Ordering::SeqCst);
+        let page = (base.as_u64() >> 12) as u8;
+        apic::send_init(apic_id);
+        pit::sleep(10);
+        for _ in 0..2 {
+            apic::send_startup(apic_id, page);
+            if wait_ready(1) {
+                return true;
+            }
+        }
+        wait_ready(AP_TIMEOUT_MS)
+    }
+}
+
+impl Drop for Trampoline {
+    fn drop(&mut self) {
+        mem::unmap(VirtAddr::new(self.frame.start_address().as_u64()));
+        frames::deallocate(self.frame);
+    }
+}
+
+fn wait_ready(millis: u64) -> bool {
+    let start = pit::uptime();
+    while !AP_READY.load(Ordering::SeqCst) {
+        if pit::uptime() - start > millis {
+            return false;
+        }
+        interrupts::enable_and_hlt();
+    }
+    true
+}
+
+impl Cpu {
+    fn new(apic_id: u8) -> Self {
+        let selectors = gdt::selectors();
+        Self {
+            this: 0,
+            kernel_rsp: AtomicU64::new(0),
+            user_rsp: AtomicU64::new(0),
+            user_cs: selectors.user_code.0 as u64,
+            user_ss: selectors.user_data.0 as u64,
+            tss: AtomicPtr::new(ptr::null_mut()),
+            index: 0,
+            apic_id,
+            topology: cpu::topology(),
+        }
+    }
+
+    /// Sets The Stack This CPU Switches To When An Interrupt Or `syscall`
+    /// Arrives From Ring 3.
+    pub fn set_kernel_stack(&self, top: VirtAddr) {
+        unsafe {
+            (*self.tss.load(Ordering::Relaxed)).privilege_stack_table[0] = top;
+        }
+        self.kernel_rsp.store(top.as_u64(), Ordering::Relaxed);
+    }
+}
+
+/// Fills In The Calling CPU's Block, Points Both GS Bases At It & Adds It To
+/// The Online List. `tss` Is The CPU's Loaded TSS.
+fn publish(cpu: &'static mut Cpu, tss: *mut TaskStateSegment) {
+    cpu.this = cpu as *const Cpu as u64;
+    if apic::is_enabled() {
+        cpu.apic_id = apic::local_id();
+    }
+    cpu.topology = cpu::topology();
+    cpu.tss = AtomicPtr::new(tss);
+    cpu.kernel_rsp = AtomicU64::new(unsafe { (*tss).privilege_stack_table[0].as_u64() });
+    GsBase::write(VirtAddr::new(cpu.this));
+    KernelGsBase::write(VirtAddr::new(cpu.this));
+
+    interrupts::without_interrupts(|| {
+        let mut online = ONLINE.lock();
+        cpu.index = online.len();
+        online.push(cpu);
+    });
+}
+
+/// First Rust Code Run By An AP, On The Stack Given By The Trampoline. It
+/// Becomes The AP's Idle Thread Once The Scheduler Is Running.
+extern "C" fn ap_entry(cpu: &'static mut Cpu) -> ! {
+    unsafe {
+        Cr4::write_raw(BSP_CR4.load(Ordering::Relaxed));
+    }
+    let tss = gdt::init_ap();
+    idt::init_ap();
+    syscall::init();
+    apic::enable_local();
+
+    publish(cpu, tss);
+    AP_READY.store(true, Ordering::SeqCst);
+
+    while !task::is_running() {
+        core::hint::spin_loop();
+    }
+    task::scheduler::init_ap();
+    apic::start_timer(idt::LOCAL_TIMER_VECTOR, AP_TIMER_MS);
+
+    loop {
+        interrupts::enable_and_hlt();
+    }
+}
+
+/// The Calling CPU's Block, `None` Before [`init`].
+pub fn current() -> Option<&'static Cpu> {
+    if GsBase::read().is_null() {
+        return None;
+    }
+    let cpu: u64;
+    unsafe {
+        asm!("mov {}, gs:[0]", out(reg) cpu, options(nostack, readonly, preserves_flags));
+        Some(&*(cpu as *const Cpu))
+    }
+}
+
+/// The Calling CPU's Position In The Online List, 0 Before [`init`].
+pub fn index() -> usize {
+    current().map_or(0, |cpu| cpu.index)
+}
+
+/// Swaps In The Kernel's GS Base For An Interrupt From Ring 3 & Back Out
+/// When Dropped, So Handlers Reached From User Code Find Their [`Cpu`].
+pub struct KernelGs(bool);
+
+impl KernelGs {
+    pub fn enter(frame: &InterruptStackFrame) -> Self {
+        let from_user = frame.code_segment & 3 == 3;
+        if from_user {
+            unsafe { GS::swap() }
+        }
+        Self(from_user)
+    }
+}
+
+impl Drop for KernelGs {
+    fn drop(&mut self) {
+        if self.0 {
+            unsafe { GS::swap() }
+        }
+    }
+}
+
+/// Makes Every Other Online CPU Flush Its TLB & Waits Until They Have, For
+/// Page Table Changes Made While Others May Have Cached The Old Entries.
+pub fn shootdown() {
+    if count() < 2 {
+        return;
+    }
+
+    interrupts::without_interrupts(|| {
+        let online = ONLINE.lock();
+        let this = apic::local_id();
+        let mut tickets = [0; MAX_CPUS];
+        for (cpu, ticket) in online.iter().zip(&mut tickets) {
+            if cpu.apic_id != this {
+                *ticket = FLUSHES_REQUESTED[cpu.apic_id as usize].fetch_add(1, Ordering::SeqCst) + 1;
+                apic::send_nmi(cpu.apic_id);
+            }
+        }
+        for (cpu, &ticket) in online.iter().zip(&tickets) {
+            while FLUSHES_DONE[cpu.apic_id as usize].load(Ordering::SeqCst) < ticket {
+                core::hint::spin_loop();
+            }
+        }
+    });
+}
+
+/// Flushes The TLB If Another CPU Asked For It, Returning `false` If The NMI
+/// Came From Somewhere Else. Requests Made Before The Flush Starts Are Done.
+pub(super) fn handle_nmi() -> bool {
+    if !apic::is_enabled() {
+        return false;
+    }
+
+    let id = apic::local_id() as usize;
+    let requested = FLUSHES_REQUESTED[id].load(Ordering::SeqCst);
+    if requested == FLUSHES_DONE[id].load(Ordering::SeqCst) {
+        return false;
+    }
+    flush_tlb();
+    FLUSHES_DONE[id].fetch_max(requested, Ordering::SeqCst);
+    true
+}
+
+/// Flushes Every TLB Entry, Global Ones Included.
+fn flush_tlb() {
+    let cr4 = Cr4::read();
+    if cr4.contains(Cr4Flags::PAGE_GLOBAL) {
+        unsafe {
+            Cr4::write(cr4 - Cr4Flags::PAGE_GLOBAL);
+            Cr4::write(cr4);
+        }
+    } else {
+        tlb::flush_all();
+    }
+}
+
+pub fn count() -> usize {
+    interrupts::without_interrupts(|| ONLINE.lock().len())
+}
+
+pub fn csh_cpus(_: ShellArgs) -> ExitCode {
+    let cpus: Vec<&'static Cpu> = interrupts::without_interrupts(|| ONLINE.lock().clone());
+    let current = current().map(|cpu| cpu.index);
+
+    for cpu in cpus {
+        println!(
+            "CPU {}: APIC ID {} - {} Thread(s) Per Core, {} Per Package{}",
+            cpu.index,
+            cpu.apic_id,
+            cpu.topology.threads_per_core,
+            cpu.topology.threads_per_package,
+            if current == Some(cpu.index) { " (Current)" } else { "" }
+        );
+    }
+    ExitCode::Ok
+}
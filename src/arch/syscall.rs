@@ -0,0 +1,107 @@
+//! The `syscall`/`sysretq` Entry Path.
+//!
+//! `syscall` Leaves The User Stack In RSP, So The Stub Switches To The
+//! Running Thread's Kernel Stack & Builds The Same `Context` The `int 0x80`
+//! Stub Does, Letting Both Paths Share `crate::syscall::dispatch`. The Stack
+//! Pointers & User Selectors Live In The Calling CPU's [`smp::Cpu`] Block,
+//! Swapped In Through GS.
+use core::arch::global_asm;
+
+use x86_64::{
+    registers::{
+        model_specific::{Efer, EferFlags, LStar, SFMask, Star},
+        rflags::RFlags,
+    },
+    VirtAddr,
+};
+
+use super::{gdt, smp};
+
+global_asm!(
+    ".global syscall_fast_entry",
+    "syscall_fast_entry:",
+    // SFMASK Masks Interrupts But Not NMIs, Which Run On An IST Stack &
+    // Don't Use GS, So One Arriving Before The Stack Switch Is Harmless.
+    "    swapgs",
+    "    mov gs:[{user_rsp}], rsp",
+    "    mov rsp, gs:[{kernel_rsp}]",
+    // Build The Frame An Interrupt Would Have Pushed, RCX & R11 Hold The
+    // Return Address & RFLAGS.
+    "    push qword ptr gs:[{user_ss}]",
+    "    push qword ptr gs:[{user_rsp}]",
+    "    push r11",
+    "    push qword ptr gs:[{user_cs}]",
+    "    push rcx",
+    "    push rax",
+    "    push rbx",
+    "    push rcx",
+    "    push rdx",
+    "    push rsi",
+    "    push rdi",
+    "    push rbp",
+    "    push r8",
+    "    push r9",
+    "    push r10",
+    "    push r11",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    "    mov rdi, rsp",
+    "    call syscall_switch",
+    "    mov rsp, rax",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop r11",
+    "    pop r10",
+    "    pop r9",
+    "    pop r8",
+    "    pop rbp",
+    "    pop rdi",
+    "    pop rsi",
+    "    pop rdx",
+    "    pop rcx",
+    "    pop rbx",
+    "    pop rax",
+    "    mov rcx, [rsp]",
+    // SYSRET To A Non-Canonical RCX Raises #GP In Ring 0 With The User's
+    // RSP Already Loaded. IRETQ Faults On The Kernel Stack Instead.
+    "    mov r11, rcx",
+    "    shr r11, 47",
+    "    jnz 1f",
+    "    mov r11, [rsp + 16]",
+    "    mov rsp, [rsp + 24]",
+    "    swapgs",
+    "    sysretq",
+    "1:",
+    "    swapgs",
+    "    iretq",
+    kernel_rsp = const smp::CPU_KERNEL_RSP,
+    user_rsp = const smp::CPU_USER_RSP,
+    user_cs = const smp::CPU_USER_CS,
+    user_ss = const smp::CPU_USER_SS,
+);
+
+extern "C" {
+    fn syscall_fast_entry();
+}
+
+/// Enables `syscall` On The Calling CPU & Points It At `syscall_fast_entry`.
+pub fn init() {
+    let selectors = gdt::selectors();
+    Star::write(
+        selectors.user_code,
+        selectors.user_data,
+        selectors.kernel_code,
+        selectors.kernel_data,
+    )
+    .expect("Invalid GDT Layout For SYSCALL/SYSRET");
+    LStar::write(VirtAddr::new(syscall_fast_entry as *const () as u64));
+    SFMask::write(RFlags::INTERRUPT_FLAG | RFlags::TRAP_FLAG | RFlags::DIRECTION_FLAG);
+
+    unsafe {
+        Efer::update(|flags| flags.insert(EferFlags::SYSTEM_CALL_EXTENSIONS));
+    }
+}
@@ -1,4 +1,8 @@
-use core::{arch::asm, fmt::Display, ops::Range};
+use core::{
+    arch::asm,
+    fmt::Display,
+    ops::{Index, IndexMut, Range},
+};
 
 use bit_field::BitField;
 use x86_64::{registers::control::Cr3, structures::paging::PhysFrame, PhysAddr, VirtAddr};
@@ -22,7 +26,13 @@ pub const PTF_ACCESSED_BIT: usize = 5;
 pub const PTF_DIRTY_BIT: usize = 6;
 pub const PTF_HUGE_PAGE_BIT: usize = 7;
 pub const PTF_GLOBAL_BIT: usize = 8;
+/// Available To Software, Marks A Read-Only Page That Is Copied On Write.
+pub const PTF_COPY_ON_WRITE_BIT: usize = 9;
+/// Available To Software, Marks A Page Of Shared Memory That Stays Shared
+/// Across A Fork Instead Of Being Copied.
+pub const PTF_SHARED_BIT: usize = 10;
 pub const PTF_ADDRESS_BITS: Range<usize> = 12..52;
+pub const PTF_NO_EXECUTE_BIT: usize = 63;
 
 impl PageTableEntry {
     pub fn empty() -> Self {
@@ -37,6 +47,20 @@ impl PageTableEntry {
         return self.0.get_bits(PTF_ADDRESS_BITS) << 12;
     }
 
+    /// The Raw Entry, Including The Flag Bits Above Bit 7.
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+
+    /// Points The Entry At `addr` With The Raw Flag Bits `flags`.
+    pub fn set(&mut self, addr: PhysAddr, flags: u64) {
+        self.0 = (addr.as_u64() & !0xFFF & !(1 << PTF_NO_EXECUTE_BIT)) | flags;
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+
     pub fn flags(&self) -> u8 {
         return (self.0 & 0xFF) as u8;
     }
@@ -54,11 +78,7 @@ impl PageTableEntry {
     }
 
     pub fn get_bit(&self, bit: usize) -> bool {
-        let mask = 1 << bit;
-        let result = self.flags() & mask;
-        klog!("{:08b} & {:08b} = {}\n", self.flags(), mask, result);
-
-        result != 0
+        self.0.get_bit(bit)
     }
 
     pub fn is_present(&self) -> bool {
@@ -148,6 +168,20 @@ impl PageTable {
     }
 }
 
+impl Index<usize> for PageTable {
+    type Output = PageTableEntry;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.entries[index]
+    }
+}
+
+impl IndexMut<usize> for PageTable {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.entries[index]
+    }
+}
+
 impl Display for PageTableEntry {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(
@@ -1,62 +1,93 @@
-use crate::arch::x64::instructions::port;
+use crate::arch::x64::instructions::{interrupts::without_interrupts, port};
 use crate::device::BlockAddr;
+use crate::locked::Locked;
 
 use crate::pit::sleep;
 use crate::println;
 use crate::sprint;
 
 use alloc::collections::BTreeMap;
-use alloc::vec::Vec;
 use core::mem::size_of;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use alloc::string::String;
 use bit_field::BitField;
+use lazy_static::lazy_static;
 use port::Port;
 use port::PortReadOnly as PortR;
 use port::PortWriteOnly as PortW;
+use spin::Mutex;
+use spinning_top::SpinlockGuard;
 
 pub const BLOCK_SIZE: usize = 512;
 
 pub const CACHE_LINE_SIZE: u32 = 8;
 
-static mut BLOCK_CACHE: BTreeMap<(u8, u8, u32), [u8; BLOCK_SIZE]> = BTreeMap::new();
+/// Only Held With Interrupts Disabled & Never Across Disk I/O.
+static BLOCK_CACHE: Mutex<BTreeMap<(u8, u8, u32), [u8; BLOCK_SIZE]>> = Mutex::new(BTreeMap::new());
 
-static mut CACHE_MISSES: usize = 0;
-static mut CACHE_HITS: usize = 0;
-static mut TOTAL_OPS: usize = 0;
+static CACHE_MISSES: AtomicUsize = AtomicUsize::new(0);
+static CACHE_HITS: AtomicUsize = AtomicUsize::new(0);
+static TOTAL_OPS: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    /// Each Bus's Registers, Locked For A Whole Command Sequence So Threads
+    /// Can't Interleave Drive Selects & Commands.
+    static ref BUS_REGISTERS: [Locked<Registers>; 2] = [
+        Locked::new(get_register(0)),
+        Locked::new(get_register(1)),
+    ];
+}
+
+fn cache_insert(bus: u8, drive: u8, block: BlockAddr, data: Sector) {
+    without_interrupts(|| BLOCK_CACHE.lock().insert((bus, drive, block), data));
+}
+
+fn cache_get(bus: u8, drive: u8, block: BlockAddr) -> Option<Sector> {
+    without_interrupts(|| BLOCK_CACHE.lock().get(&(bus, drive, block)).copied())
+}
 
 #[allow(deprecated)]
 pub fn write_block(bus: u8, drive: u8, block: BlockAddr, data: &[u8]) -> EmptyResult {
-    unsafe {
-        let mut buf = [0; BLOCK_SIZE];
-        buf.copy_from_slice(data);
-        BLOCK_CACHE.insert((bus, drive, block), buf);
-        write(bus, drive, block, data)?;
-        Ok(())
-    }
+    let mut buf = [0; BLOCK_SIZE];
+    buf.copy_from_slice(data);
+    cache_insert(bus, drive, block, buf);
+    write(bus, drive, block, data)
 }
 
 #[allow(deprecated)]
 pub fn read_block(bus: u8, drive: u8, addr: u32) -> Result<[u8; BLOCK_SIZE], ()> {
-    unsafe {
-        TOTAL_OPS += 1;
-        if BLOCK_CACHE.contains_key(&(bus, drive, addr)) {
-            CACHE_HITS += 1;
-            return Ok(*BLOCK_CACHE.get(&(bus, drive, addr)).unwrap());
-        } else {
-            let data = read(bus, drive, addr)?;
-            BLOCK_CACHE.insert((bus, drive, addr), data);
+    TOTAL_OPS.fetch_add(1, Ordering::Relaxed);
+    if let Some(data) = cache_get(bus, drive, addr) {
+        CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+        return Ok(data);
+    }
 
-            for i in 0..CACHE_LINE_SIZE {
-                if let Ok(data_next) = read(bus, drive, addr + i) {
-                    BLOCK_CACHE.insert((bus, drive, addr + i), data_next);
-                }
-            }
+    let data = read(bus, drive, addr)?;
+    cache_insert(bus, drive, addr, data);
 
-            CACHE_MISSES += 1;
-            return Ok(data);
+    for i in 1..CACHE_LINE_SIZE {
+        if let Ok(data_next) = read(bus, drive, addr + i) {
+            cache_insert(bus, drive, addr + i, data_next);
         }
     }
+
+    CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
+    Ok(data)
+}
+
+/// Writes Straight To The Drive & Drops Any Cached Copy, For Data Like Swap
+/// That Shouldn't Be Kept In The Cache.
+#[allow(deprecated)]
+pub fn write_block_uncached(bus: u8, drive: u8, block: BlockAddr, data: &[u8]) -> EmptyResult {
+    without_interrupts(|| BLOCK_CACHE.lock().remove(&(bus, drive, block)));
+    write(bus, drive, block, data)
+}
+
+/// Reads Straight From The Drive Without Filling The Cache.
+#[allow(deprecated)]
+pub fn read_block_uncached(bus: u8, drive: u8, block: BlockAddr) -> Result<Sector, ()> {
+    read(bus, drive, block)
 }
 
 pub fn cache_stats() {
@@ -68,15 +99,15 @@ pub fn cache_stats() {
 }
 
 pub fn hits() -> usize {
-    unsafe { CACHE_HITS }
+    CACHE_HITS.load(Ordering::Relaxed)
 }
 
 pub fn misses() -> usize {
-    unsafe { CACHE_MISSES }
+    CACHE_MISSES.load(Ordering::Relaxed)
 }
 
 pub fn total_ops() -> usize {
-    unsafe { TOTAL_OPS }
+    TOTAL_OPS.load(Ordering::Relaxed)
 }
 
 pub fn availability() -> f64 {
@@ -107,6 +138,7 @@ enum Command {
     Read = 0x20,
     Write = 0x30,
     Indentify = 0xEC,
+    FlushCache = 0xE7,
 }
 
 #[allow(unused)]
@@ -174,7 +206,7 @@ impl Registers {
     pub fn poll(&mut self, bit: Status, val: bool) -> EmptyResult {
         let start = crate::pit::uptime();
         while unsafe { self.status.read().get_bit(bit as usize) != val } {
-            if crate::pit::uptime() - start > (crate::pit::polling_rate() * 1) {
+            if crate::pit::uptime() - start > 1000 {
                 self.debug();
                 return Err(());
             }
@@ -291,6 +323,27 @@ impl Registers {
         }
     }
 
+    /// Has The Drive Write Its Own Write Cache Out To The Disk.
+    pub fn flush_cache(&mut self, drive: u8) -> Result<(), ()> {
+        self.set_active_drive(drive)?;
+        if self.status() == 0 {
+            // Drive Nonexistent
+            return Err(());
+        }
+
+        unsafe { self.command.write(Command::FlushCache as u8) };
+        sleep(1);
+        self.poll(Status::BSY, false)?;
+        self.clear_interrupt();
+
+        if self.is_error() {
+            self.debug();
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn indentify(&mut self, drive: u8) -> Result<DiskInfo, ()> {
         self.set_active_drive(drive)?;
         self.write_command_params(drive, 0)?;
@@ -329,52 +382,62 @@ impl DiskInfo {
     }
 }
 
-static mut BUSES: Option<Vec<Bus>> = None;
-
 pub fn init() {
-    let mut buses = Vec::new();
-
-    buses.push(Bus::bus_0());
-    buses.push(Bus::bus_1());
-
-    unsafe {BUSES = Some(buses);}
+    lazy_static::initialize(&BUS_REGISTERS);
 }
 
-pub fn bus<'a>(index: u8) -> Option<&'a Bus> {
-    if index > 1 { return None; }
-
-    unsafe {
-        if let Some(buses) = &BUSES {
-            buses.get(index as usize)
-        } else {
-            return None;
-        }
+pub fn bus(index: u8) -> Option<Bus> {
+    match index {
+        0 => Some(Bus::bus_0()),
+        1 => Some(Bus::bus_1()),
+        _ => None,
     }
 }
 
+/// Locks `bus` For A Command Sequence. Commands Sleep Between Steps, So
+/// This Yields Rather Than Spins While Another Thread Has The Bus.
+fn lock_bus(bus: u8) -> SpinlockGuard<'static, Registers> {
+    BUS_REGISTERS[bus.min(1) as usize].lock_yielding()
+}
+
 #[deprecated]
 /// MARKED FOR INTERNAL USE ONLY
 pub fn read(bus: u8, drive: u8, block: u32) -> Result<Sector, ()> {
-    let mut bus = get_register(bus);
-    bus.read_block(drive, block)
+    lock_bus(bus).read_block(drive, block)
 }
 
 #[deprecated]
 /// MARKED FOR INTERNAL USE ONLY
 pub fn write(bus: u8, drive: u8, block: u32, data: &[u8]) -> EmptyResult {
-    let mut bus = get_register(bus);
-    bus.write_block(drive, block, data)
+    lock_bus(bus).write_block(drive, block, data)
+}
+
+/// Flushes The Drive's Write Cache. Writes Through The Block Cache Reach
+/// The Drive Straight Away, But May Sit In Its Cache.
+pub fn flush(bus: u8, drive: u8) -> EmptyResult {
+    lock_bus(bus).flush_cache(drive)
+}
+
+/// Flushes Every Drive, Returning How Many Were Flushed.
+pub fn flush_all() -> usize {
+    let mut flushed = 0;
+    for bus in 0..2 {
+        for drive in 0..2 {
+            if flush(bus, drive).is_ok() {
+                flushed += 1;
+            }
+        }
+    }
+    flushed
 }
 
 pub fn get_sector_count(bus: u8, drive: u8) -> Result<usize, ()> {
-    let mut bus = get_register(bus);
-    let info = bus.indentify(drive)?;
+    let info = lock_bus(bus).indentify(drive)?;
     Ok(info.sectors)
 }
 
 pub fn info(bus: u8, drive: u8) -> Result<DiskInfo, ()> {
-    let mut bus = get_register(bus);
-    bus.indentify(drive)
+    lock_bus(bus).indentify(drive)
 }
 
 /// bus #0 => ($1F0, $3F6, 14)
@@ -388,8 +451,10 @@ fn get_register(bus: u8) -> Registers {
 }
 
 
+/// A Handle To A Bus, Commands Go Through The Bus Lock.
+#[derive(Debug, Clone, Copy)]
 pub struct Bus {
-    registers: Registers,
+    index: u8,
     active_drive: DriveIndex,
 }
 
@@ -410,15 +475,15 @@ pub enum DriveIndex {
 impl Bus {
 
     pub fn bus_0() -> Self {
-        Self::new(BUS_0)
+        Self::new(0)
     }
     pub fn bus_1() -> Self {
-        Self::new(BUS_1)
+        Self::new(1)
     }
 
-    pub fn new(values: BusValues) -> Self {
+    pub fn new(index: u8) -> Self {
         Self {
-            registers: Registers::new(values.0, values.1, values.2),
+            index,
             active_drive: DriveIndex::Primary
         }
     }
@@ -432,10 +497,10 @@ impl Bus {
     }
 
     pub fn write(&mut self, addr: BlockAddr, data: &[u8]) -> DiskResult<()> {
-        self.registers.write_block(self.active_drive as u8,addr, data)
+        lock_bus(self.index).write_block(self.active_drive as u8, addr, data)
     }
 
     pub fn read(&mut self, addr: BlockAddr) -> DiskResult<Sector> {
-        self.registers.read_block(self.active_drive as u8, addr)
+        lock_bus(self.index).read_block(self.active_drive as u8, addr)
     }
 }
\ No newline at end of file
@@ -0,0 +1,215 @@
+//! Monotonic Clock With Nanosecond Resolution.
+//!
+//! Reads The Invariant TSC When The CPU Has One, Else A 64 Bit HPET, Else
+//! Falls Back To Counting PIT Ticks. The TSC Is Calibrated Against The HPET
+//! When There Is One & Against The PIT Otherwise. Counter Deltas Are Scaled
+//! By A 32.32 Fixed Point Multiplier, So Reads Don't Divide. Until `init`
+//! Runs The Clock Counts PIT Ticks, & It Carries On From There Afterwards.
+use conquer_once::spin::OnceCell;
+use core::{
+    arch::x86_64::_rdtsc,
+    fmt::Display,
+    ops::{Add, Sub},
+};
+use x86_64::instructions::interrupts;
+
+use crate::{
+    arch::{
+        cpu,
+        hpet::{self, Hpet},
+    },
+    klog, pit,
+};
+
+pub use core::time::Duration;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const FEMTOS_PER_NANO: u64 = 1_000_000;
+
+/// How Long The TSC Is Measured For.
+const CALIBRATION_MILLIS: u64 = 50;
+
+static CLOCK: OnceCell<Clock> = OnceCell::uninit();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Pit,
+    Hpet,
+    Tsc,
+}
+
+struct Clock {
+    source: Source,
+    /// Counter Value When The Clock Took Over From The PIT.
+    base: u64,
+    /// Nanoseconds Counted By The PIT Before That.
+    offset: u64,
+    /// Nanoseconds Per Counter Tick, Shifted Left By 32.
+    scale: u64,
+}
+
+impl Clock {
+    fn new(source: Source, hz: u64) -> Self {
+        Self {
+            source,
+            base: read_counter(source),
+            offset: pit_nanos(),
+            scale: ((NANOS_PER_SEC as u128) << 32).div_ceil(hz.max(1) as u128) as u64,
+        }
+    }
+
+    fn nanos(&self) -> u64 {
+        let delta = read_counter(self.source).wrapping_sub(self.base);
+        self.offset + ((delta as u128 * self.scale as u128) >> 32) as u64
+    }
+}
+
+fn read_counter(source: Source) -> u64 {
+    match source {
+        Source::Tsc => unsafe { _rdtsc() },
+        Source::Hpet => hpet::get().map_or(0, Hpet::counter),
+        Source::Pit => pit::ticks(),
+    }
+}
+
+fn pit_nanos() -> u64 {
+    pit::ticks() * NANOS_PER_SEC / pit::polling_rate().max(1)
+}
+
+/// Picks & Calibrates The Best Time Source. Needs The Heap & The PIT Running.
+pub fn init() {
+    let hpet = hpet::initialize();
+
+    let (source, hz) = if cpu::has_invariant_tsc() {
+        (Source::Tsc, hpet.map_or_else(tsc_hz_from_pit, tsc_hz_from_hpet))
+    } else {
+        match hpet {
+            Some(hpet) if hpet.is_wide() => (
+                Source::Hpet,
+                NANOS_PER_SEC * FEMTOS_PER_NANO / hpet.period().max(1),
+            ),
+            _ => (Source::Pit, pit::polling_rate()),
+        }
+    };
+
+    // Taken Together So The Clock Carries On From The PIT Time.
+    interrupts::without_interrupts(|| CLOCK.init_once(|| Clock::new(source, hz)));
+    klog!("Clock: {:?} At {} kHz\n", source, hz / 1000);
+}
+
+/// Counts TSC Ticks Over `CALIBRATION_MILLIS` Of The HPET.
+fn tsc_hz_from_hpet(hpet: &Hpet) -> u64 {
+    let hpet_ticks = CALIBRATION_MILLIS * 1_000_000 * FEMTOS_PER_NANO / hpet.period().max(1);
+
+    interrupts::without_interrupts(|| {
+        let start = hpet.counter();
+        let tsc = unsafe { _rdtsc() };
+        let mut elapsed = 0;
+        while elapsed < hpet_ticks {
+            core::hint::spin_loop();
+            elapsed = hpet.counter().wrapping_sub(start) & counter_mask(hpet);
+        }
+        let tsc = unsafe { _rdtsc() } - tsc;
+
+        let femtos = elapsed as u128 * hpet.period() as u128;
+        (tsc as u128 * (NANOS_PER_SEC * FEMTOS_PER_NANO) as u128 / femtos) as u64
+    })
+}
+
+fn counter_mask(hpet: &Hpet) -> u64 {
+    if hpet.is_wide() {
+        u64::MAX
+    } else {
+        u32::MAX as u64
+    }
+}
+
+/// Counts TSC Ticks Over `CALIBRATION_MILLIS` Worth Of PIT Ticks, Which
+/// Must Be Arriving.
+fn tsc_hz_from_pit() -> u64 {
+    let rate = pit::polling_rate().max(1);
+    let ticks = (CALIBRATION_MILLIS * rate / 1000).max(1);
+
+    // Start On A Tick Edge.
+    let start = pit::ticks();
+    while pit::ticks() == start {
+        interrupts::enable_and_hlt();
+    }
+
+    let start = pit::ticks();
+    let tsc = unsafe { _rdtsc() };
+    while pit::ticks() - start < ticks {
+        interrupts::enable_and_hlt();
+    }
+    let tsc = unsafe { _rdtsc() } - tsc;
+    tsc * rate / ticks
+}
+
+/// The Source `monotonic_ns` Reads.
+pub fn source() -> Source {
+    CLOCK.get().map_or(Source::Pit, |clock| clock.source)
+}
+
+/// Nanoseconds Since Boot, Never Goes Backwards.
+pub fn monotonic_ns() -> u64 {
+    match CLOCK.get() {
+        Some(clock) => clock.nanos(),
+        None => pit_nanos(),
+    }
+}
+
+/// Milliseconds Since Boot.
+pub fn monotonic_ms() -> u64 {
+    monotonic_ns() / 1_000_000
+}
+
+/// A Point On The Monotonic Clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub fn now() -> Self {
+        Self(monotonic_ns())
+    }
+
+    /// Time Since `earlier`, Zero If `earlier` Is Later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    /// Nanoseconds Since Boot.
+    pub fn as_nanos(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant {
+        Instant(self.0 + rhs.as_nanos() as u64)
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration {
+        self.duration_since(rhs)
+    }
+}
+
+impl Display for Instant {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{}.{:09}",
+            self.0 / NANOS_PER_SEC,
+            self.0 % NANOS_PER_SEC
+        )
+    }
+}
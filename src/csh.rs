@@ -4,7 +4,7 @@ use alloc::{
     vec::Vec,
 };
 
-use crate::{device, input, mem, println, sprint, time, terminal};
+use crate::{arch, device, input, mem, println, process, sprint, task, time, terminal};
 
 pub mod cat;
 pub mod ls;
@@ -39,6 +39,7 @@ pub fn init() -> Result<(), ()> {
     add_program("help", help)?;
     add_program("time", time::time)?;
     add_program("shutdown", shutdown)?;
+    add_program("reboot", reboot)?;
     add_program("ls", ls::main)?;
     add_program("delete", delete::main)?;
     add_program("create", create::main)?;
@@ -46,6 +47,14 @@ pub fn init() -> Result<(), ()> {
     add_program("cls", clear_screen)?;
     add_program("clr", clear_screen)?;
     add_program("clear", clear_screen)?;
+    add_program("ps", task::ps)?;
+    add_program("exec", process::exec_main)?;
+    add_program("wait", process::wait_main)?;
+    add_program("swapon", mem::swap::csh_swapon)?;
+    add_program("memmap", mem::inspect::csh_memmap)?;
+    add_program("pt", mem::inspect::csh_pt)?;
+    add_program("cpus", arch::smp::csh_cpus)?;
+    add_program("irqstat", arch::irq::csh_irqstat)?;
 
     Ok(())
 }
@@ -78,6 +87,14 @@ impl ExitCode {
             &Self::Error(code) => code.unix(),
         }
     }
+
+    /// The Inverse Of `unix`, Codes Are Kept As `ErrorCode::Other`.
+    pub fn from_unix(code: u8) -> Self {
+        match code {
+            0 => Self::Ok,
+            code => Self::Error(ErrorCode::Other(code)),
+        }
+    }
 }
 
 impl ErrorCode {
@@ -103,13 +120,29 @@ pub fn exec(cmd: &str) -> ExitCode {
     if cmd == "exit" {
         return ExitCode::Ok;
     }
-    let parts: ShellArgs = cmd
+    let mut parts: ShellArgs = cmd
         .to_string()
         .split_ascii_whitespace()
         .map(|s| s.to_string())
         .collect::<Vec<String>>();
-    let ec = if let Some(main) = unsafe { PROGS.get(&parts[0]) } {
-        main(parts)
+    let background = parts.last().is_some_and(|last| last == "&");
+    if background {
+        parts.pop();
+    }
+    if parts.is_empty() {
+        return ExitCode::Ok;
+    }
+    let ec = if let Some(&main) = unsafe { PROGS.get(&parts[0]) } {
+        if background {
+            let name = parts[0].clone();
+            let job = task::spawn(&name, move || {
+                main(parts);
+            });
+            println!("[{}] {}", job.id().0, name);
+            ExitCode::Ok
+        } else {
+            main(parts)
+        }
     } else {
         sprint!("No Such Program - '{}'\n", parts[0]);
         ExitCode::Error(ErrorCode::CommandNotFound)
@@ -156,3 +189,9 @@ fn shutdown(_: ShellArgs) -> ExitCode {
     #[allow(unreachable_code)]
     ExitCode::Ok
 }
+
+fn reboot(_: ShellArgs) -> ExitCode {
+    crate::reboot();
+    #[allow(unreachable_code)]
+    ExitCode::Ok
+}
@@ -9,7 +9,10 @@ pub fn main(args: ShellArgs) -> ExitCode {
         return ExitCode::Error(ErrorCode::Usage)
     }
 
-    simple_fat::delete_file(&args[1]);
+    if !simple_fat::delete_file(&args[1]) {
+        println!("Can't Delete {}, It Doesn't Exist Or Is Mapped", args[1]);
+        return ExitCode::Error(ErrorCode::General)
+    }
 
     ExitCode::Ok
 }
\ No newline at end of file
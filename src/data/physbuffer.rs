@@ -1,34 +1,23 @@
 use core::{ops::{Index, IndexMut}, slice::SliceIndex};
 
-use crate::mem;
+use crate::mem::{self, dma::DmaBuffer};
 
-use alloc::{vec, vec::{Vec}, sync::Arc};
-use spin::mutex::Mutex;
+use alloc::sync::Arc;
 use x86_64::VirtAddr;
 
+// A DmaBuffer shared between clones of a device, freed once every clone is dropped
 #[derive(Clone)]
 pub struct PhysBuf {
-    buf: Arc<Mutex<Vec<u8>>>,
+    buf: Arc<DmaBuffer>,
 }
 
 impl PhysBuf {
     pub fn new(len: usize) -> Self {
-        Self::from(vec![0; len])
-    }
-
-    // Realloc vec until it uses a chunk of contiguous physical memory
-    fn from(vec: Vec<u8>) -> Self {
-        let buffer_len = vec.len() - 1;
-        let memory_len = phys_addr(&vec[buffer_len]) - phys_addr(&vec[0]);
-        if buffer_len == memory_len as usize {
-            Self { buf: Arc::new(Mutex::new(vec)) }
-        } else {
-            Self::from(vec.clone()) // Clone vec and try again
-        }
+        Self { buf: Arc::new(DmaBuffer::new(len).expect("Out Of DMA Memory")) }
     }
 
     pub fn addr(&self) -> u64 {
-        phys_addr(&self.buf.lock()[0])
+        self.buf.phys().as_u64()
     }
 }
 
@@ -58,14 +47,12 @@ impl core::ops::Deref for PhysBuf {
     type Target = [u8];
 
     fn deref(&self) -> &[u8] {
-        let vec = self.buf.lock();
-        unsafe { alloc::slice::from_raw_parts(vec.as_ptr(), vec.len()) }
+        &self.buf
     }
 }
 
 impl core::ops::DerefMut for PhysBuf {
     fn deref_mut(&mut self) -> &mut [u8] {
-        let mut vec = self.buf.lock();
-        unsafe { alloc::slice::from_raw_parts_mut(vec.as_mut_ptr(), vec.len()) }
+        unsafe { alloc::slice::from_raw_parts_mut(self.buf.virt().as_mut_ptr(), self.buf.len()) }
     }
-}
\ No newline at end of file
+}
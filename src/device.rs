@@ -1,6 +1,7 @@
 use core::{ops::Range, fmt::{Write, Display}};
 
 use alloc::{string::String, vec::Vec};
+use spin::Mutex;
 
 use crate::{
     ata::{self, Sector, BLOCK_SIZE},
@@ -13,7 +14,9 @@ const MEM_DISK_SIZE: usize = (4 << 20) / BLOCK_SIZE;
 
 pub type BlockAddr = u32;
 
-static mut MOUNT: Option<Device> = None;
+/// ATA Devices Are Copied Out Of The Lock Before Any I/O, Their Bus Lock
+/// Serializes Access. The Memory Disk Is Used Under It.
+static MOUNT: Mutex<Option<Device>> = Mutex::new(None);
 
 pub fn mount_main(args: ShellArgs) -> ExitCode {
     if args.len() < 2 {
@@ -214,54 +217,49 @@ pub fn mount(dev: Device) {
         sprint!("[{}]: Cannot Mount Device\n", module_path!());
         return;
     }
-    unsafe {
-        MOUNT = Some(dev);
+    *MOUNT.lock() = Some(dev);
+}
+
+/// Runs `f` On The Mounted Device, Without Holding The Lock Across Disk I/O.
+fn with_mount<R>(f: impl FnOnce(&mut Device) -> Result<R, ()>) -> Result<R, ()> {
+    let mut mount = MOUNT.lock();
+    match mount.as_mut().ok_or(())? {
+        &mut Device::Ata(bus, drive) => {
+            drop(mount);
+            f(&mut Device::Ata(bus, drive))
+        }
+        dev => f(dev),
     }
 }
 
 pub fn read(block: BlockAddr) -> Result<[u8; 512], ()> {
-    if let Some(dev) = unsafe { &mut MOUNT } {
-        dev.read(block)
-    } else {
-        Err(())
-    }
+    with_mount(|dev| dev.read(block))
 }
 
 pub fn read_block(block: BlockAddr) -> Result<Block, ()> {
-    if let Some(dev) = unsafe { &mut MOUNT } {
-        let data = dev.read(block)?;
-        Ok(Block::from(block, data))
-    } else {
-        Err(())
-    }
+    let data = read(block)?;
+    Ok(Block::from(block, data))
 }
 
 pub fn write(block: BlockAddr, data: &[u8]) -> Result<(), ()> {
-    if let Some(dev) = unsafe { &mut MOUNT } {
-        dev.write(block, data)
-    } else {
-        Err(())
-    }
+    with_mount(|dev| dev.write(block, data))
 }
 
 pub fn write_block(addr: BlockAddr, block: Block) -> Result<(), ()> {
-    if let Some(dev) = unsafe { &mut MOUNT } {
-        dev.write(addr, block.data())
-    } else {
-        Err(())
-    }
+    write(addr, block.data())
 }
 
 pub fn info() -> Result<DeviceInfo, ()> {
-    if let Some(dev) = unsafe { &mut MOUNT } {
-        dev.info()
-    } else {
-        Err(())
-    }
+    with_mount(|dev| dev.info())
 }
 
 pub fn is_mounted() -> bool {
-    unsafe { MOUNT.is_some() }
+    MOUNT.lock().is_some()
+}
+
+/// Whether The ATA Drive `(bus, drive)` Is The Mounted Device.
+pub fn is_mounted_ata(bus: u8, drive: u8) -> bool {
+    matches!(*MOUNT.lock(), Some(Device::Ata(b, d)) if (b, d) == (bus, drive))
 }
 
 
@@ -1,5 +1,191 @@
-use elf_rs::{self, Elf};
+//! Loading & Running ELF64 Executables.
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use elf_rs::{self, Elf, ElfFile, ProgramHeaderFlags, ProgramType};
+
+use crate::mem::{
+    address_space::{AddressSpace, MapError, PAGE_SIZE},
+    regions::{Region, RegionKind},
+    PTFlags, VirtAddr,
+};
+
+/// The Address Just Above A Program's Stack.
+pub const STACK_TOP: u64 = 0x0000_7FFF_FFFF_0000;
+/// Size Of A Program's Stack (64 KB).
+pub const STACK_SIZE: u64 = PAGE_SIZE * 16;
+/// Where Non-Canonical Addresses Begin.
+const LOWER_HALF_END: u64 = 0x0000_8000_0000_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecError {
+    NotFound,
+    InvalidElf,
+    /// Only 64-Bit x86 Executables Can Be Run.
+    Unsupported,
+    /// Part Of The Program Overlaps An Existing Mapping Or Kernel Memory.
+    AddressInUse(u64),
+}
 
 pub fn parse(bin: &[u8]) -> Result<Elf, elf_rs::Error> {
     Elf::from_bytes(bin)
 }
+
+/// Where A Loaded Program Starts Executing.
+#[derive(Debug, Clone, Copy)]
+pub struct Program {
+    pub entry: u64,
+    pub stack_pointer: u64,
+}
+
+/// Maps Every PT_LOAD Segment Of `bytes` Into `space` & Builds A Stack
+/// Holding `args` & `env`.
+pub fn load(
+    bytes: &[u8],
+    args: &[String],
+    env: &[String],
+    space: &mut AddressSpace,
+) -> Result<Program, ExecError> {
+    let elf = parse(bytes).map_err(|_| ExecError::InvalidElf)?;
+    if !matches!(elf, Elf::Elf64(_)) {
+        return Err(ExecError::Unsupported);
+    }
+
+    // Segments May Share A Page, So Merge Their Flags Before Mapping.
+    let mut pages: BTreeMap<u64, PTFlags> = BTreeMap::new();
+    let segments: Vec<_> = elf
+        .program_header_iter()
+        .filter(|header| matches!(header.ph_type(), ProgramType::LOAD))
+        .collect();
+
+    for header in segments.iter() {
+        if header.filesz() > header.memsz() {
+            return Err(ExecError::InvalidElf);
+        }
+        // Both Ends Must Be Canonical & In The Lower Half, So Every Page In
+        // Between Is Too.
+        let start = VirtAddr::try_new(header.vaddr()).map_err(|_| ExecError::InvalidElf)?;
+        let end = start
+            .as_u64()
+            .checked_add(header.memsz())
+            .and_then(|end| VirtAddr::try_new(end).ok())
+            .filter(|end| end.as_u64() < LOWER_HALF_END)
+            .ok_or(ExecError::InvalidElf)?;
+        reserve(
+            &mut pages,
+            start.as_u64(),
+            end.as_u64(),
+            segment_flags(header.flags()),
+        );
+    }
+
+    for (&addr, &flags) in pages.iter() {
+        space
+            .map(VirtAddr::new(addr), flags)
+            .map_err(|err| match err {
+                MapError::AlreadyMapped(addr)
+                | MapError::HugePage(addr)
+                | MapError::Reserved(addr) => {
+                    ExecError::AddressInUse(addr)
+                }
+            })?;
+    }
+
+    // The Stack Is Mapped A Page At A Time As It Grows, With A Guard Page
+    // Below It To Catch Overflows.
+    let stack = STACK_TOP - STACK_SIZE;
+    let flags =
+        PTFlags::PRESENT | PTFlags::USER_ACCESSIBLE | PTFlags::WRITABLE | PTFlags::NO_EXECUTE;
+    let guard = Region::new(
+        stack - PAGE_SIZE,
+        stack,
+        RegionKind::Guard,
+        PTFlags::empty(),
+    );
+    if !space.add_region(Region::new(stack, STACK_TOP, RegionKind::Stack, flags))
+        || !space.add_region(guard)
+    {
+        return Err(ExecError::AddressInUse(stack));
+    }
+
+    // Pages Start Out Zeroed, So Everything Past `filesz` (.bss) Is Already
+    // Cleared.
+    for header in segments.iter() {
+        let data = header
+            .offset()
+            .checked_add(header.filesz())
+            .and_then(|end| bytes.get(header.offset() as usize..end as usize))
+            .ok_or(ExecError::InvalidElf)?;
+        space.write(VirtAddr::new(header.vaddr()), data);
+    }
+
+    Ok(Program {
+        entry: elf.elf_header().entry_point(),
+        stack_pointer: setup_stack(space, args, env),
+    })
+}
+
+/// Records The Pages Covering `start..end`, Merging `flags` Into Any Page
+/// That Is Shared With Another Segment.
+fn reserve(pages: &mut BTreeMap<u64, PTFlags>, start: u64, end: u64, flags: PTFlags) {
+    let mut addr = start & !(PAGE_SIZE - 1);
+    while addr < end {
+        let page_flags = pages
+            .entry(addr)
+            .or_insert(PTFlags::PRESENT | PTFlags::USER_ACCESSIBLE | PTFlags::NO_EXECUTE);
+
+        if flags.contains(PTFlags::WRITABLE) {
+            page_flags.insert(PTFlags::WRITABLE);
+        }
+        if !flags.contains(PTFlags::NO_EXECUTE) {
+            page_flags.remove(PTFlags::NO_EXECUTE);
+        }
+        addr += PAGE_SIZE;
+    }
+}
+
+/// Lays Out The Stack The System V ABI Expects At The Entry Point: `argc`,
+/// `argv[]`, `NULL`, `envp[]`, `NULL` & An Empty Auxiliary Vector, With The
+/// Strings Themselves Stored Above. Returns The Initial Stack Pointer.
+fn setup_stack(space: &mut AddressSpace, args: &[String], env: &[String]) -> u64 {
+    let mut sp = STACK_TOP;
+    let mut push_str = |space: &mut AddressSpace, s: &String| {
+        sp -= s.len() as u64 + 1;
+        space.write(VirtAddr::new(sp), s.as_bytes());
+        space.write(VirtAddr::new(sp + s.len() as u64), &[0]);
+        sp
+    };
+
+    let argv: Vec<u64> = args.iter().map(|arg| push_str(space, arg)).collect();
+    let envp: Vec<u64> = env.iter().map(|var| push_str(space, var)).collect();
+
+    let mut words = Vec::with_capacity(argv.len() + envp.len() + 5);
+    words.push(argv.len() as u64);
+    words.extend_from_slice(&argv);
+    words.push(0);
+    words.extend_from_slice(&envp);
+    words.push(0);
+    // AT_NULL
+    words.push(0);
+    words.push(0);
+
+    sp &= !0xF;
+    if words.len() % 2 == 1 {
+        sp -= 8;
+    }
+    sp -= words.len() as u64 * 8;
+
+    for (index, word) in words.iter().enumerate() {
+        space.write(VirtAddr::new(sp + index as u64 * 8), &word.to_le_bytes());
+    }
+    sp
+}
+
+fn segment_flags(flags: ProgramHeaderFlags) -> PTFlags {
+    let mut page_flags = PTFlags::PRESENT | PTFlags::USER_ACCESSIBLE;
+    if flags.contains(ProgramHeaderFlags::WRITE) {
+        page_flags |= PTFlags::WRITABLE;
+    }
+    if !flags.contains(ProgramHeaderFlags::EXECUTE) {
+        page_flags |= PTFlags::NO_EXECUTE;
+    }
+    page_flags
+}
@@ -34,6 +34,9 @@ pub enum Command {
     ReadPort2 = 0xD3,
 
     SelfTest = 0xAA,
+
+    /// Pulses The CPU Reset Line.
+    PulseReset = 0xFE,
 }
 
 impl Into<u8> for Command {
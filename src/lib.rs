@@ -16,6 +16,7 @@ pub mod api;
 
 pub mod arch;
 pub mod ata;
+pub mod clock;
 pub mod csh;
 pub mod data;
 pub mod device;
@@ -30,7 +31,10 @@ pub mod mem;
 pub mod net;
 pub mod pit;
 pub mod pci;
+pub mod process;
 pub mod serial;
+pub mod syscall;
+pub mod task;
 pub mod terminal;
 pub mod time;
 pub mod vfs;
@@ -84,9 +88,12 @@ pub fn boot(info: &'static mut BootInfo) {
         input::init();
         let physical_memory_offset = info.physical_memory_offset.into_option().unwrap();
         let phys_mem_offset = VirtAddr::new(physical_memory_offset);
-        mem::allocator::BitmapAllocator::init(info);
         mem::setup_from(info);
         mem::init(phys_mem_offset, &*info.memory_regions);
+        clock::init();
+        arch::initialize_apic();
+        arch::smp::init();
+        task::init();
 
         pci::init();
 
@@ -128,7 +135,21 @@ pub fn boot(info: &'static mut BootInfo) {
 }
 
 pub fn shutdown() -> ! {
+    sync();
     arch::acpi::shutdown();
 
     loop {}
 }
+
+/// Writes Back Every Process's File Mappings & Flushes The Drives' Write
+/// Caches.
+pub fn sync() {
+    let failed = process::sync_all();
+    let drives = ata::flush_all();
+    klog!("Synced: {} Drive(s) Flushed, {} Process(es) Failed\n", drives, failed);
+}
+
+pub fn reboot() -> ! {
+    sync();
+    arch::reset()
+}
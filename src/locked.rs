@@ -16,6 +16,21 @@ impl<T> Locked<T> {
         self.item.lock()
     }
 
+    pub fn try_lock(&self) -> Option<SpinlockGuard<'_, T>> {
+        self.item.try_lock()
+    }
+
+    /// Locks, Yielding To Other Threads While It's Held. For Locks Kept
+    /// Across Disk I/O, Where Spinning Would Only Burn The Holder's Time.
+    pub fn lock_yielding(&self) -> SpinlockGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.item.try_lock() {
+                return guard;
+            }
+            crate::task::yield_now();
+        }
+    }
+
     pub fn force_unlock(&self) {
         unsafe {
             self.item.force_unlock();
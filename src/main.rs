@@ -14,7 +14,7 @@ use alloc::{vec::Vec};
 #[cfg(not(test))]
 use bootloader::entry_point;
 use bootloader::BootInfo;
-use cashew_kernel::{ata, graphics_2d::*, kerr, println, csh, net::{self}};
+use cashew_kernel::{ata, graphics_2d::*, kerr, println, csh, net::{self}, task};
 
 
 #[cfg(not(test))]
@@ -32,6 +32,8 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
         
 
         net::init();
+        task::executor::spawn(net::poll_async());
+        drop(task::spawn("executor", || task::executor::run()));
 
 
         ata::cache_stats();
@@ -7,9 +7,13 @@ use bootloader::{
 };
 use conquer_once::spin::OnceCell;
 use x86_64::{
-    instructions::interrupts::without_interrupts,
+    instructions::{interrupts::without_interrupts, tlb},
     registers::control::Cr3,
-    structures::paging::{Mapper, PageTable, PhysFrame, Size4KiB},
+    structures::paging::{
+        mapper::{MapToError, TranslateResult, UnmapError},
+        page_table::PageTableEntry,
+        Mapper, PageTable, PhysFrame, Size2MiB, Size4KiB,
+    },
 };
 
 pub use x86_64::structures::paging::Page;
@@ -18,27 +22,41 @@ pub use x86_64::structures::paging::{OffsetPageTable, Translate};
 pub use x86_64::PhysAddr;
 pub use x86_64::VirtAddr;
 
+pub mod address_space;
 pub mod allocator;
+pub mod dma;
 pub mod frames;
+pub mod inspect;
 pub mod mapper;
+pub mod mmap;
 pub mod pagetable;
+pub mod regions;
+pub mod shared;
+pub mod slab;
+pub mod swap;
+#[cfg(feature = "debug")]
+pub mod tracker;
 
 static mut PHYSICAL_OFFSET: Option<VirtAddr> = None;
 pub static mut MEMORY_MAP: Option<&MemoryRegions> = None;
 pub static MEMORY_SIZE: AtomicU64 = AtomicU64::new(0);
 
 use crate::{
+    arch::smp,
     csh::{ExitCode, ShellArgs},
     klog,
     locked::Locked,
-    mem::allocator::HEAP_SIZE,
+    mem::allocator::HEAP_MAX_SIZE,
     pit, println, sprint,
 };
 
-use self::{allocator::BitmapAllocator, frames::BootInfoFrameAllocator};
+use self::frames::KernelFrameAllocator;
 
 static PAGE_TABLE: OnceCell<Locked<OffsetPageTable>> = OnceCell::uninit();
 
+/// The PML4 Set Up By The Bootloader, Loaded Whenever A Kernel Thread Runs.
+static KERNEL_PAGE_TABLE: OnceCell<PhysFrame> = OnceCell::uninit();
+
 pub fn setup_from(info: &'static BootInfo) {
     unsafe {
         MEMORY_MAP.replace(&info.memory_regions);
@@ -63,25 +81,50 @@ pub fn init(phys_offset: VirtAddr, regions: &'static [MemoryRegion]) {
     unsafe { PHYSICAL_OFFSET = Some(phys_offset) }
 
     unsafe {
+        KERNEL_PAGE_TABLE.init_once(|| Cr3::read().0);
         PAGE_TABLE.init_once(|| Locked::new(pagetable::init(phys_offset)));
 
+        frames::init(regions, phys_offset);
+
         let mut mapper = PAGE_TABLE.get().unwrap().lock();
-        let mut frame_allocator = BootInfoFrameAllocator::init(regions);
-        allocator::init_heap(&mut *mapper, &mut frame_allocator).expect("Memory Init Failed");
+        allocator::init_heap(&mut *mapper, &mut KernelFrameAllocator).expect("Memory Init Failed");
     }
+    regions::init();
 }
 
 pub fn virt_to_phys(addr: VirtAddr) -> Option<PhysAddr> {
-    if let Some(mapper) = PAGE_TABLE.get() {
-        without_interrupts(|| {
-            let mapper = mapper.lock();
-            mapper.translate_addr(addr)
-        })
+    if PAGE_TABLE.get().is_some() {
+        with_page_table(|mapper| mapper.translate_addr(addr))
     } else {
         None
     }
 }
 
+/// Runs `f` On The Kernel's Page Tables. The Lock Is Only Held With
+/// Interrupts Disabled, So A Thread Holding It Can't Be Preempted By One
+/// Spinning On It.
+fn with_page_table<R>(f: impl FnOnce(&mut OffsetPageTable<'static>) -> R) -> R {
+    without_interrupts(|| f(&mut PAGE_TABLE.get().expect("Memory Not Initialized").lock()))
+}
+
+pub fn kernel_page_table() -> PhysFrame {
+    *KERNEL_PAGE_TABLE.get().expect("Memory Not Initialized")
+}
+
+/// Returns The Flags Of The Page Table Entry Mapping `addr` In The Active
+/// Address Space.
+pub fn page_flags(addr: VirtAddr) -> Option<PTFlags> {
+    let offset = unsafe { PHYSICAL_OFFSET? };
+    without_interrupts(|| {
+        let table = (offset + Cr3::read().0.start_address().as_u64()).as_mut_ptr();
+        let mapper = unsafe { OffsetPageTable::new(&mut *table, offset) };
+        match mapper.translate(addr) {
+            TranslateResult::Mapped { flags, .. } => Some(flags),
+            _ => None,
+        }
+    })
+}
+
 pub fn phys_to_virt(addr: PhysAddr) -> Option<VirtAddr> {
     if let Some(offset) = unsafe { PHYSICAL_OFFSET } {
         return Some(VirtAddr::new_truncate(addr.as_u64() + offset.as_u64()));
@@ -90,6 +133,7 @@ pub fn phys_to_virt(addr: PhysAddr) -> Option<VirtAddr> {
     }
 }
 
+#[inline(never)]
 pub fn malloc(size: usize, align: usize) -> NonNull<u8> {
     allocator::_malloc(Layout::from_size_align(size, align).expect("Alignment Error"))
 }
@@ -102,6 +146,16 @@ pub fn free() -> usize {
     allocator::_free()
 }
 
+/// Bytes Of Heap Currently Mapped, Which Only Grows.
+pub fn heap_size() -> usize {
+    allocator::_size()
+}
+
+/// The Most Heap Memory Ever In Use At Once.
+pub fn high_water() -> usize {
+    allocator::_high_water()
+}
+
 pub fn dealloc(ptr: NonNull<u8>, size: usize, align: usize) {
     allocator::_dealloc(
         ptr,
@@ -214,32 +268,97 @@ pub fn pagetable_at_frame(frame: PhysFrame) -> &'static PageTable {
     unsafe { &*ptr }
 }
 
+const HUGE_PAGE_SIZE: u64 = 2 << 20;
+
 pub fn map_virt_to_phys(virt: VirtAddr, phys: PhysAddr, flags: PTFlags) {
-    let mut mapper = PAGE_TABLE.get().unwrap().lock();
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&*MEMORY_MAP.unwrap()) };
     let frame: PhysFrame<Size4KiB> = PhysFrame::containing_address(phys);
     let page: Page<Size4KiB> = Page::containing_address(virt);
+    match with_page_table(|mapper| map_page(mapper, page, frame, flags)) {
+        Ok(()) => {}
+        Err(MapToError::FrameAllocationFailed) => panic!("Failed To Allocate Frame!"),
+        Err(MapToError::ParentEntryHugePage) => klog!("Huge Page Already Mapped...\n"),
+        Err(MapToError::PageAlreadyMapped(frame)) => {
+            panic!("Frame {:?} Is Already Mapped!", frame)
+        }
+    }
+}
+
+/// Maps One 4 KiB Page. If A 2 MiB Page Already Covers It, That Page Is Split
+/// First So Only This Page Changes.
+fn map_page(
+    mapper: &mut OffsetPageTable,
+    page: Page<Size4KiB>,
+    frame: PhysFrame<Size4KiB>,
+    flags: PTFlags,
+) -> Result<(), MapToError<Size4KiB>> {
     unsafe {
-        match mapper.map_to(page, frame, flags, &mut frame_allocator) {
-            Ok(flsh) => flsh.flush(),
-            Err(ec) => match ec {
-                x86_64::structures::paging::mapper::MapToError::FrameAllocationFailed => {
-                    panic!("Failed To Allocate Frame!")
-                }
-                x86_64::structures::paging::mapper::MapToError::ParentEntryHugePage => {
-                    klog!("Huge Page Already Mapped...\n")
+        match mapper.map_to(page, frame, flags, &mut KernelFrameAllocator) {
+            Err(MapToError::ParentEntryHugePage)
+                if split_huge_page(mapper, page.start_address()) =>
+            {
+                if let Ok((_, flush)) = mapper.unmap(page) {
+                    flush.ignore();
                 }
-                x86_64::structures::paging::mapper::MapToError::PageAlreadyMapped(frame) => {
-                    panic!("Frame {:?} Is Already Mapped!", frame)
-                }
-            },
+                mapper
+                    .map_to(page, frame, flags, &mut KernelFrameAllocator)
+                    .map(|flush| flush.flush())
+            }
+            result => result.map(|flush| flush.flush()),
         }
     }
 }
 
+/// Replaces The 2 MiB Page Covering `virt` With A Table Of 512 4 KiB Pages
+/// Mapping The Same Memory With The Same Flags. Returns `false` If `virt`
+/// Isn't Mapped By A 2 MiB Page.
+fn split_huge_page(mapper: &mut OffsetPageTable, virt: VirtAddr) -> bool {
+    let page: Page<Size4KiB> = Page::containing_address(virt);
+    let p3 = match table_below(&mapper.level_4_table()[page.p4_index()]) {
+        Some(table) => table,
+        None => return false,
+    };
+    let p2 = match table_below(&p3[page.p3_index()]) {
+        Some(table) => table,
+        None => return false,
+    };
+
+    let entry = &mut p2[page.p2_index()];
+    if !entry.flags().contains(PTFlags::PRESENT | PTFlags::HUGE_PAGE) {
+        return false;
+    }
+    let frame = match frames::allocate() {
+        Some(frame) => frame,
+        None => return false,
+    };
+
+    let flags = entry.flags() - PTFlags::HUGE_PAGE;
+    let table: &mut PageTable =
+        unsafe { &mut *phys_to_virt(frame.start_address()).unwrap().as_mut_ptr() };
+    for (index, small) in table.iter_mut().enumerate() {
+        small.set_addr(entry.addr() + index as u64 * 4096, flags);
+    }
+
+    // The Table Entry Must Allow Anything One Of Its Pages Might Later Need.
+    let table_flags = PTFlags::PRESENT | PTFlags::WRITABLE | (flags & PTFlags::USER_ACCESSIBLE);
+    entry.set_addr(frame.start_address(), table_flags);
+
+    // One INVLPG Anywhere In The 2 MiB Page Drops Its TLB Entry.
+    tlb::flush(page.start_address());
+    address_space::sync_active();
+    true
+}
+
+/// The Table An Entry Points To, If It Points To A Table Rather Than A Page.
+fn table_below(entry: &PageTableEntry) -> Option<&'static mut PageTable> {
+    let flags = entry.flags();
+    if !flags.contains(PTFlags::PRESENT) || flags.contains(PTFlags::HUGE_PAGE) {
+        return None;
+    }
+    Some(unsafe { &mut *phys_to_virt(entry.addr())?.as_mut_ptr() })
+}
+
 pub fn map_virt(virt: VirtAddr, flags: PTFlags) {
-    let _frame_allocator = unsafe { BootInfoFrameAllocator::init(&*MEMORY_MAP.unwrap()) };
-    if let Some(frame) = BitmapAllocator::next_free() {
+    if let Some(frame) = frames::allocate() {
         klog!(
             "Mapping ${:x} --> ${:x}\n",
             virt,
@@ -251,26 +370,71 @@ pub fn map_virt(virt: VirtAddr, flags: PTFlags) {
     }
 }
 
-pub fn map_contiguous(size: usize, virt: VirtAddr, phys: PhysAddr, flags: PTFlags) {
-    let start_page: Page<Size4KiB> = Page::containing_address(virt);
-    let end = VirtAddr::new(virt.as_u64() + size as u64);
-
-    let end_page: Page<Size4KiB> = Page::containing_address(end);
+/// Maps `virt` To A Newly Allocated Frame.
+pub fn map_fresh(virt: VirtAddr, flags: PTFlags) {
+    let frame = frames::allocate().expect("Out Of Physical Memory");
+    map_virt_to_phys(virt, frame.start_address(), flags);
+}
 
-    let pages = Page::range(start_page, end_page);
-    let mut mapper = PAGE_TABLE.get().unwrap().lock();
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&*MEMORY_MAP.unwrap()) };
+/// Maps `size` Bytes At `virt` To `phys`. With `huge`, Every Stretch Where
+/// Both Addresses Are 2 MiB Aligned & At Least 2 MiB Remain Is Mapped With
+/// A Single 2 MiB Page, Which Is Split Again If Part Of It Is Remapped.
+pub fn map_contiguous(size: usize, virt: VirtAddr, phys: PhysAddr, flags: PTFlags, huge: bool) {
+    let end = VirtAddr::new(virt.as_u64() + size as u64).align_down(4096u64);
+    let mut virt = virt.align_down(4096u64);
+    let mut phys_addr = phys.align_down(4096u64);
+
+    with_page_table(|mapper| while virt < end {
+        let fits = huge
+            && virt.is_aligned(HUGE_PAGE_SIZE)
+            && phys_addr.is_aligned(HUGE_PAGE_SIZE)
+            && end - virt >= HUGE_PAGE_SIZE;
+
+        // A Table Left Behind By Earlier 4 KiB Mappings Forces 4 KiB Pages.
+        if fits {
+            let page: Page<Size2MiB> = Page::containing_address(virt);
+            let frame: PhysFrame<Size2MiB> = PhysFrame::containing_address(phys_addr);
+            let mapped = unsafe { mapper.map_to(page, frame, flags, &mut KernelFrameAllocator) };
+            if let Ok(flush) = mapped {
+                flush.flush();
+                virt += HUGE_PAGE_SIZE;
+                phys_addr += HUGE_PAGE_SIZE;
+                continue;
+            }
+        }
 
-    let mut phys_addr = phys;
-    for page in pages {
+        let page: Page<Size4KiB> = Page::containing_address(virt);
         let frame: PhysFrame<Size4KiB> = PhysFrame::containing_address(phys_addr);
-        unsafe {
-            mapper
-                .map_to(page, frame, flags, &mut frame_allocator)
-                .expect("Failed To Map Pages")
-                .flush();
-        }
+        map_page(mapper, page, frame, flags).expect("Failed To Map Pages");
+        virt += 4096u64;
         phys_addr += 4096u64;
+    })
+}
+
+/// Unmaps The 4 KiB Page Containing `virt`, Splitting A 2 MiB Page First.
+fn unmap_page(mapper: &mut OffsetPageTable, virt: VirtAddr) -> Option<PhysFrame> {
+    let page: Page<Size4KiB> = Page::containing_address(virt);
+    let result = match mapper.unmap(page) {
+        Err(UnmapError::ParentEntryHugePage) if split_huge_page(mapper, virt) => mapper.unmap(page),
+        result => result,
+    };
+    let (frame, flush) = result.ok()?;
+    flush.flush();
+    Some(frame)
+}
+
+/// Removes The Mapping For The Page Containing `virt`, The Frame Is Not Freed.
+pub fn unmap(virt: VirtAddr) {
+    with_page_table(|mapper| unmap_page(mapper, virt));
+    smp::shootdown();
+}
+
+/// Removes The Mapping For The Page Containing `virt` & Frees Its Frame
+/// Once No CPU Can Still Reach It.
+pub fn unmap_free(virt: VirtAddr) {
+    if let Some(frame) = with_page_table(|mapper| unmap_page(mapper, virt)) {
+        smp::shootdown();
+        frames::deallocate(frame);
     }
 }
 
@@ -278,29 +442,83 @@ pub fn is_mapped(virt: VirtAddr) -> bool {
     virt_to_phys(virt).is_some()
 }
 
-pub fn csh_stats(_: ShellArgs) -> ExitCode {
+pub fn csh_stats(args: ShellArgs) -> ExitCode {
+    if args.iter().any(|arg| arg == "--leaks") {
+        return csh_leaks();
+    }
+
     println!("=== MEM STATS ===");
     let free = free();
     let used = used();
-    let total = HEAP_SIZE;
+    let total = heap_size();
+    let peak = high_water();
 
     let mut width = 0;
 
     width = width.max(free.to_string().chars().count());
     width = width.max(used.to_string().chars().count());
     width = width.max(total.to_string().chars().count());
+    width = width.max(HEAP_MAX_SIZE.to_string().chars().count());
 
     println!("Used:  {:0>w$} Bytes", used, w = width as usize);
     println!("Free:  {:0>w$} Bytes", free, w = width as usize);
     println!("Total: {:0>w$} Bytes", total, w = width as usize);
+    println!("Peak:  {:0>w$} Bytes", peak, w = width as usize);
+    println!("Limit: {:0>w$} Bytes", HEAP_MAX_SIZE, w = width as usize);
 
     sprint!("Used:  {:0>w$} Bytes\n", used, w = width as usize);
     sprint!("Free:  {:0>w$} Bytes\n", free, w = width as usize);
     sprint!("Total: {:0>w$} Bytes\n", total, w = width as usize);
+    sprint!("Peak:  {:0>w$} Bytes\n", peak, w = width as usize);
+
+    for cache in slab::stats().iter().filter(|cache| cache.slabs > 0) {
+        println!(
+            "Slab {:>4} B: {} Of {} Objects In Use ({} Slabs)",
+            cache.size, cache.used, cache.total, cache.slabs
+        );
+    }
+
+    let (free_frames, total_frames) = frames::stats();
+    println!("Frames: {} Free Of {} ({} KB Free)", free_frames, total_frames, free_frames * 4);
+    if swap::is_enabled() {
+        let (used_slots, total_slots) = swap::stats();
+        println!("Swap: {} KB Used Of {} KB", used_slots * 4, total_slots * 4);
+    }
     println!("=================");
     ExitCode::Ok
 }
 
+/// Lists Live Allocations Grouped By Call Site, Largest First.
+#[cfg(feature = "debug")]
+fn csh_leaks() -> ExitCode {
+    let (allocations, deallocations, dropped) = tracker::counts();
+    let sites = tracker::leaks();
+    let now = pit::uptime();
+
+    println!("=== LIVE ALLOCATIONS ===");
+    for site in sites.iter() {
+        println!(
+            "{:>8} Bytes In {:>5} Allocations - {} (Oldest {}ms Ago)",
+            site.bytes,
+            site.count,
+            site.trace,
+            now - site.oldest
+        );
+    }
+    println!(
+        "{} Allocations, {} Deallocations, {} Untracked",
+        allocations, deallocations, dropped
+    );
+    println!("========================");
+    ExitCode::Ok
+}
+
+#[cfg(not(feature = "debug"))]
+fn csh_leaks() -> ExitCode {
+    println!("Allocation Tracking Needs The `debug` Feature");
+    ExitCode::Error(crate::csh::ErrorCode::General)
+}
+
 // pub fn identity_map() -> PageTable {
 //     let mut pagetable_4 = PageTable::new();
 //     for entry in pagetable_4.iter_mut() {
@@ -0,0 +1,854 @@
+//! Per-Process Address Spaces.
+//!
+//! The Kernel Lives In The Lower Half Next To User Programs (The Kernel Image
+//! & Heap Both Sit Under PML4 Entry 0), So A Fresh Address Space Starts As A
+//! Copy Of The Kernel's PML4. Whenever A User Mapping Has To Go Through A
+//! Table That Is Still Shared With The Kernel, That Table Is Copied First.
+//! Copied Tables Remember Which Kernel Table They Shadow, And Their Kernel
+//! Entries Are Refreshed Each Time The Address Space Is Activated So Later
+//! Kernel Mappings Stay Visible.
+//!
+//! Private Pages Can Be Written To Swap (See [`super::swap`]) When Physical
+//! Memory Runs Out, & Are Read Back When The Program Next Touches Them.
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use x86_64::{
+    registers::control::{Cr3, Cr3Flags},
+    structures::paging::{PageTableFlags as PTFlags, PhysFrame},
+    PhysAddr, VirtAddr,
+};
+
+use super::{
+    frames, mmap,
+    regions::{self, Fault, Region, RegionTable},
+    swap,
+};
+use crate::arch::smp::{self, MAX_CPUS};
+use crate::arch::vmm::{
+    PageTable, PageTableEntry, PTF_ACCESSED_BIT, PTF_COPY_ON_WRITE_BIT, PTF_DIRTY_BIT,
+    PTF_HUGE_PAGE_BIT, PTF_SHARED_BIT,
+};
+
+pub const PAGE_SIZE: u64 = 4096;
+
+/// Pages Swapped Out At Once When A Frame Can't Be Allocated.
+const SWAP_BATCH: usize = 16;
+
+#[repr(C, align(4096))]
+pub struct PageBuffer(pub [u8; PAGE_SIZE as usize]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// The Page Is Already Mapped, Either By The Kernel Or This Address Space.
+    AlreadyMapped(u64),
+    /// A Huge Page Covers The Address.
+    HugePage(u64),
+    /// The Address Belongs To The Kernel, See [`regions::overlaps_kernel`].
+    Reserved(u64),
+}
+
+/// The Address Space In Each CPU's CR3, Null While The Kernel's Own Tables
+/// Are Loaded. Indexed By [`smp::index`].
+static ACTIVE: [AtomicPtr<AddressSpace>; MAX_CPUS] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_CPUS];
+
+/// The Physical Address Bits Of An Entry, Everything Else Is Flags.
+const ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// Flags Given To Intermediate Tables, Leaf Entries Restrict Access Further.
+const TABLE_FLAGS: u64 =
+    PTFlags::PRESENT.bits() | PTFlags::WRITABLE.bits() | PTFlags::USER_ACCESSIBLE.bits();
+
+struct OwnedTable {
+    /// Reached Through The Physical Memory Mapping.
+    table: &'static mut PageTable,
+    phys: PhysAddr,
+    /// The Kernel Table This Is A Copy Of.
+    shadows: Option<PhysAddr>,
+    /// One Bit Per Entry That Belongs To This Address Space.
+    private: [u64; 8],
+}
+
+impl OwnedTable {
+    /// Allocates A Table Holding A Copy Of `shadows`, Or Empty Without One.
+    fn new(shadows: Option<PhysAddr>) -> Self {
+        let phys = frames::allocate()
+            .expect("Out Of Physical Memory")
+            .start_address();
+        let table = table_at(phys);
+        *table = match shadows {
+            Some(kernel) => *table_at(kernel),
+            None => PageTable::new(),
+        };
+        Self {
+            table,
+            phys,
+            shadows,
+            private: [0; 8],
+        }
+    }
+
+    fn is_private(&self, index: usize) -> bool {
+        self.private[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn set_private(&mut self, index: usize, private: bool) {
+        if private {
+            self.private[index / 64] |= 1 << (index % 64);
+        } else {
+            self.private[index / 64] &= !(1 << (index % 64));
+        }
+    }
+
+    /// Copies Every Kernel-Owned Entry From The Shadowed Table.
+    fn sync(&mut self) {
+        if let Some(kernel) = self.shadows {
+            let kernel = table_at(kernel);
+            for index in 0..512 {
+                if !self.is_private(index) {
+                    self.table[index] = kernel[index];
+                }
+            }
+        }
+    }
+}
+
+impl Drop for OwnedTable {
+    fn drop(&mut self) {
+        frames::deallocate(PhysFrame::containing_address(self.phys));
+    }
+}
+
+/// A Page Of User Memory. After A Fork It Is Shared By Several Address
+/// Spaces, The `Arc` Reference Count Being The Number Mapping It.
+pub struct Frame {
+    phys: PhysAddr,
+}
+
+impl Frame {
+    fn allocate() -> Self {
+        Self::try_allocate().expect("Out Of Physical Memory")
+    }
+
+    fn try_allocate() -> Option<Self> {
+        let frame = frames::allocate()?;
+        Some(Self {
+            phys: frame.start_address(),
+        })
+    }
+
+    pub(super) fn zeroed() -> Self {
+        Self::try_zeroed().expect("Out Of Physical Memory")
+    }
+
+    fn try_zeroed() -> Option<Self> {
+        let mut frame = Self::try_allocate()?;
+        frame.buffer_mut().0.fill(0);
+        Some(frame)
+    }
+
+    pub fn phys(&self) -> PhysAddr {
+        self.phys
+    }
+
+    pub fn data(&self) -> &[u8; PAGE_SIZE as usize] {
+        &self.buffer().0
+    }
+
+    pub(super) fn data_mut(&mut self) -> &mut [u8; PAGE_SIZE as usize] {
+        &mut self.buffer_mut().0
+    }
+
+    fn buffer(&self) -> &PageBuffer {
+        let virt = super::phys_to_virt(self.phys).expect("Physical Memory Is Not Mapped");
+        unsafe { &*virt.as_ptr() }
+    }
+
+    fn buffer_mut(&mut self) -> &mut PageBuffer {
+        let virt = super::phys_to_virt(self.phys).expect("Physical Memory Is Not Mapped");
+        unsafe { &mut *virt.as_mut_ptr() }
+    }
+}
+
+impl Clone for Frame {
+    fn clone(&self) -> Self {
+        let mut copy = Self::allocate();
+        copy.buffer_mut().0 = self.buffer().0;
+        copy
+    }
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        frames::deallocate(PhysFrame::containing_address(self.phys));
+    }
+}
+
+/// A Page Written To Swap, With The Entry Flags To Restore.
+#[derive(Debug, Clone, Copy)]
+struct Swapped {
+    slot: u32,
+    flags: u64,
+}
+
+pub struct AddressSpace {
+    /// `tables[0]` Is The PML4.
+    tables: Vec<OwnedTable>,
+    pages: BTreeMap<u64, Arc<Frame>>,
+    /// Where Faults On Unmapped Pages Are Allowed.
+    regions: RegionTable,
+    swapped: BTreeMap<u64, Swapped>,
+    /// Slots Still Holding An Unmodified Copy Of A Page Read Back From
+    /// Swap, So Evicting It Again Needs No Write.
+    backing: BTreeMap<u64, u32>,
+    /// The Page The Clock Sweep Looks At Next.
+    hand: u64,
+}
+
+impl AddressSpace {
+    /// Creates An Address Space Containing Only The Kernel's Mappings.
+    pub fn new() -> Self {
+        Self {
+            tables: alloc::vec![OwnedTable::new(Some(kernel_pml4()))],
+            pages: BTreeMap::new(),
+            regions: RegionTable::new(),
+            swapped: BTreeMap::new(),
+            backing: BTreeMap::new(),
+            hand: 0,
+        }
+    }
+
+    pub fn regions(&self) -> &RegionTable {
+        &self.regions
+    }
+
+    /// Reserves `region`, Returning `false` If It Overlaps Another Region
+    /// Or Kernel Memory.
+    pub fn add_region(&mut self, region: Region) -> bool {
+        if regions::overlaps_kernel(region.start, region.end) || !self.regions.insert(region) {
+            return false;
+        }
+        mmap::pin(&region);
+        true
+    }
+
+    pub fn remove_region(&mut self, start: u64) -> Option<Region> {
+        let region = self.regions.remove(start)?;
+        mmap::unpin(&region);
+        Some(region)
+    }
+
+    pub fn pml4(&self) -> PhysAddr {
+        self.tables[0].phys
+    }
+
+    pub fn is_active(&self) -> bool {
+        Cr3::read().0.start_address() == self.pml4()
+    }
+
+    /// Maps A Zeroed Page At `addr` With `flags`, Returning Its Contents.
+    pub fn map(&mut self, addr: VirtAddr, flags: PTFlags) -> Result<&mut PageBuffer, MapError> {
+        let page = addr.align_down(PAGE_SIZE).as_u64();
+        let frame = self.zeroed_frame();
+        self.map_frame(page, Arc::new(frame), flags.bits())?;
+        Ok(self.page_mut(addr).expect("Fresh Page Is Shared"))
+    }
+
+    /// Maps `frame` At `addr` As Shared Memory, Which Is Never Copied On
+    /// Write & Stays Shared With Children After A Fork.
+    pub fn map_shared(
+        &mut self,
+        addr: VirtAddr,
+        frame: Arc<Frame>,
+        flags: PTFlags,
+    ) -> Result<(), MapError> {
+        let page = addr.align_down(PAGE_SIZE).as_u64();
+        self.map_frame(page, frame, flags.bits() | 1 << PTF_SHARED_BIT)
+    }
+
+    /// Points `page` At `frame` With The Raw Entry Flags `flags`.
+    fn map_frame(&mut self, page: u64, frame: Arc<Frame>, flags: u64) -> Result<(), MapError> {
+        // The Kernel Never Sees A Private Leaf, So It Would Later Map Its
+        // Own Page Over The User's Frame.
+        if regions::overlaps_kernel(page, page + PAGE_SIZE) {
+            return Err(MapError::Reserved(page));
+        }
+        let leaf = self
+            .walk(page, true)?
+            .expect("Tables Are Created While Walking");
+
+        let table = &mut self.tables[leaf];
+        if table.table[p1_index(page)].is_present() {
+            return Err(MapError::AlreadyMapped(page));
+        }
+
+        table.table[p1_index(page)].set(frame.phys(), flags | PTFlags::PRESENT.bits());
+        table.set_private(p1_index(page), true);
+        self.flush(page);
+
+        self.pages.insert(page, frame);
+        Ok(())
+    }
+
+    /// Removes A Page Mapped With `map`, Its Frame Is Freed Once No Other
+    /// Address Space Maps It.
+    pub fn unmap(&mut self, addr: VirtAddr) {
+        let page = addr.align_down(PAGE_SIZE).as_u64();
+        if let Some(swapped) = self.swapped.remove(&page) {
+            swap::free(swapped.slot);
+        }
+        if let Some(slot) = self.backing.remove(&page) {
+            swap::free(slot);
+        }
+        if self.pages.remove(&page).is_none() {
+            return;
+        }
+
+        if let Ok(Some(leaf)) = self.walk(page, false) {
+            let table = &mut self.tables[leaf];
+            table.table[p1_index(page)].clear();
+            table.set_private(p1_index(page), false);
+            self.flush(page);
+        }
+    }
+
+    /// Changes The Flags Of A Page Mapped With `map`, A Page Shared By A
+    /// Fork Made Writable Stays Read-Only Until It Is Copied.
+    pub fn protect(&mut self, addr: VirtAddr, flags: PTFlags) {
+        let page = addr.align_down(PAGE_SIZE).as_u64();
+        let shared = match self.pages.get(&page) {
+            Some(frame) => Arc::strong_count(frame) > 1,
+            None => return,
+        };
+
+        if let Some(entry) = self.entry_mut(page) {
+            let mut bits = (flags | PTFlags::PRESENT).bits();
+            if entry.get_bit(PTF_SHARED_BIT) {
+                bits |= 1 << PTF_SHARED_BIT;
+            } else if shared && flags.contains(PTFlags::WRITABLE) {
+                bits = bits & !PTFlags::WRITABLE.bits() | 1 << PTF_COPY_ON_WRITE_BIT;
+            }
+
+            let phys = entry.phys_address();
+            entry.set(phys, bits);
+            self.flush(page);
+        }
+    }
+
+    pub fn is_mapped(&self, addr: VirtAddr) -> bool {
+        self.pages
+            .contains_key(&addr.align_down(PAGE_SIZE).as_u64())
+    }
+
+    /// The Contents Of A Page Mapped With `map`.
+    pub fn page(&self, addr: VirtAddr) -> Option<&[u8; PAGE_SIZE as usize]> {
+        self.pages
+            .get(&addr.align_down(PAGE_SIZE).as_u64())
+            .map(|frame| frame.data())
+    }
+
+    /// Whether The Page At `addr` Was Written Since The Last
+    /// [`AddressSpace::take_dirty`], Leaving The CPU's Dirty Flag Set.
+    pub fn is_dirty(&mut self, addr: VirtAddr) -> bool {
+        let page = addr.align_down(PAGE_SIZE).as_u64();
+        self.entry_mut(page)
+            .is_some_and(|entry| entry.get_bit(PTF_DIRTY_BIT))
+    }
+
+    /// Whether The Page At `addr` Was Written Since The Last Call, Clearing
+    /// The CPU's Dirty Flag.
+    pub fn take_dirty(&mut self, addr: VirtAddr) -> bool {
+        let page = addr.align_down(PAGE_SIZE).as_u64();
+        let entry = match self.entry_mut(page) {
+            Some(entry) if entry.get_bit(PTF_DIRTY_BIT) => entry,
+            _ => return false,
+        };
+
+        let bits = entry.value() & !ADDRESS_MASK & !(1 << PTF_DIRTY_BIT);
+        let phys = entry.phys_address();
+        entry.set(phys, bits);
+        self.flush(page);
+        true
+    }
+
+    /// Number Of Address Spaces Mapping The Frame Behind `addr`.
+    pub fn references(&self, addr: VirtAddr) -> usize {
+        self.pages
+            .get(&addr.align_down(PAGE_SIZE).as_u64())
+            .map_or(0, Arc::strong_count)
+    }
+
+    /// The Contents Of A Page Mapped With `map`, Copying It First If It Is
+    /// Shared Copy-On-Write. Pages Shared Read-Only Can't Be Modified.
+    pub fn page_mut(&mut self, addr: VirtAddr) -> Option<&mut PageBuffer> {
+        self.copy_on_write(addr);
+        let page = addr.align_down(PAGE_SIZE).as_u64();
+
+        // Writes Through The Physical Mapping Don't Set The Dirty Flag, So
+        // The Copy In Swap Can't Be Trusted Anymore.
+        if let Some(slot) = self.backing.remove(&page) {
+            swap::free(slot);
+        }
+        let frame = self.pages.get_mut(&page)?;
+        Arc::get_mut(frame).map(Frame::buffer_mut)
+    }
+
+    /// Copies `data` To `addr` Without Activating The Address Space, The
+    /// Pages Must Already Be Mapped.
+    pub fn write(&mut self, addr: VirtAddr, data: &[u8]) {
+        let mut written = 0;
+        while written < data.len() {
+            let target = addr + written as u64;
+            let offset = (target.as_u64() % PAGE_SIZE) as usize;
+            let count = (PAGE_SIZE as usize - offset).min(data.len() - written);
+
+            if !self.is_mapped(target) {
+                // Stack & Heap Pages Are Mapped On First Use.
+                let _ = self.resolve_fault(target, false, true);
+            }
+            let page = self.page_mut(target).expect("Page Not Mapped");
+            page.0[offset..offset + count].copy_from_slice(&data[written..written + count]);
+            written += count;
+        }
+    }
+
+    /// Number Of Bytes Of User Pages & Private Page Tables Held.
+    pub fn size(&self) -> usize {
+        (self.pages.len() + self.tables.len()) * PAGE_SIZE as usize
+    }
+
+    /// Number Of Bytes Of User Pages Written To Swap.
+    pub fn swapped_size(&self) -> usize {
+        self.swapped.len() * PAGE_SIZE as usize
+    }
+
+    /// Writes Up To `count` Cold Pages To Swap & Frees Their Frames,
+    /// Returning How Many Were Evicted. The Clock Sweep Clears The Accessed
+    /// Flag Of Each Page It Passes, So Only Pages Untouched Since The Last
+    /// Turn Are Chosen. Shared Memory & Frames Mapped By Several Address
+    /// Spaces Stay Resident.
+    pub fn swap_out(&mut self, count: usize) -> usize {
+        if !swap::is_enabled() {
+            return 0;
+        }
+
+        let mut evicted = 0;
+        // Two Turns, The First May Only Clear Accessed Flags.
+        for _ in 0..self.pages.len() * 2 {
+            if evicted == count {
+                break;
+            }
+
+            let next = self.pages.range(self.hand..).next();
+            let page = match next.or_else(|| self.pages.iter().next()) {
+                Some((&page, _)) => page,
+                None => break,
+            };
+            self.hand = page + PAGE_SIZE;
+            if self.evict(page) {
+                evicted += 1;
+            }
+        }
+        evicted
+    }
+
+    /// Writes `page` To Swap Unless It Was Used Recently, Clearing Its
+    /// Accessed Flag. Returns `true` If The Page Was Evicted.
+    fn evict(&mut self, page: u64) -> bool {
+        match self.pages.get(&page) {
+            Some(frame) if Arc::strong_count(frame) == 1 => {}
+            _ => return false,
+        }
+        let entry = match self.entry_mut(page) {
+            Some(entry) if !entry.get_bit(PTF_SHARED_BIT) => *entry,
+            _ => return false,
+        };
+
+        let flags = entry.value() & !ADDRESS_MASK;
+        if entry.get_bit(PTF_ACCESSED_BIT) {
+            self.entry_mut(page)
+                .expect("Mapped Page Without An Entry")
+                .set(entry.phys_address(), flags & !(1 << PTF_ACCESSED_BIT));
+            self.flush(page);
+            return false;
+        }
+
+        let slot = match self.backing.remove(&page) {
+            Some(slot) if !entry.get_bit(PTF_DIRTY_BIT) => slot,
+            backing => {
+                let slot = match backing.or_else(swap::allocate) {
+                    Some(slot) => slot,
+                    None => return false,
+                };
+                if swap::write(slot, self.pages[&page].data()).is_err() {
+                    swap::free(slot);
+                    return false;
+                }
+                slot
+            }
+        };
+
+        let leaf = self.walk(page, false).ok().flatten();
+        let table = &mut self.tables[leaf.expect("Mapped Page Without A Table")];
+        table.table[p1_index(page)].clear();
+        table.set_private(p1_index(page), false);
+        self.flush(page);
+
+        self.pages.remove(&page);
+        let flags = flags & !(1 << PTF_DIRTY_BIT) & !PTFlags::PRESENT.bits();
+        self.swapped.insert(page, Swapped { slot, flags });
+        true
+    }
+
+    /// Reads The Page Holding `addr` Back From Swap. Returns `false` If It
+    /// Isn't Swapped Out.
+    fn swap_in(&mut self, addr: VirtAddr) -> Result<bool, Fault> {
+        let page = addr.align_down(PAGE_SIZE).as_u64();
+        let swapped = match self.swapped.get(&page) {
+            Some(&swapped) => swapped,
+            None => return Ok(false),
+        };
+
+        let mut frame = self.zeroed_frame();
+        swap::read(swapped.slot, frame.data_mut()).map_err(|_| Fault::Invalid)?;
+        self.swapped.remove(&page);
+        self.map_frame(page, Arc::new(frame), swapped.flags)
+            .map_err(|_| Fault::Invalid)?;
+        self.backing.insert(page, swapped.slot);
+        Ok(true)
+    }
+
+    /// A Zeroed Frame, Evicting Cold Pages When Physical Memory Is Full,
+    /// This Space's First & Then Those Of Other Processes.
+    fn zeroed_frame(&mut self) -> Frame {
+        loop {
+            if let Some(frame) = Frame::try_zeroed() {
+                return frame;
+            }
+            if self.swap_out(SWAP_BATCH) == 0 && swap::reclaim(SWAP_BATCH) == 0 {
+                panic!("Out Of Physical Memory");
+            }
+        }
+    }
+
+    /// Creates A Copy Of The Address Space's User Pages. Writable Pages Are
+    /// Shared Read-Only By Both Spaces & Copied By The First One To Write,
+    /// Except Shared Memory Which Both Keep Mapping As Before.
+    pub fn fork(&mut self) -> Self {
+        // Slots Have A Single Owner, So Both Spaces Start Resident.
+        let swapped: Vec<u64> = self.swapped.keys().copied().collect();
+        for page in swapped {
+            self.swap_in(VirtAddr::new(page))
+                .expect("Failed To Read Page From Swap");
+        }
+
+        let mut child = Self::new();
+        child.regions = self.regions.clone();
+        child.regions.iter().for_each(mmap::pin);
+        let pages: Vec<u64> = self.pages.keys().copied().collect();
+
+        for page in pages {
+            let entry = self.entry_mut(page).expect("Mapped Page Without An Entry");
+            let mut flags = entry.value() & !ADDRESS_MASK;
+            if flags & PTFlags::WRITABLE.bits() != 0 && !entry.get_bit(PTF_SHARED_BIT) {
+                flags = flags & !PTFlags::WRITABLE.bits() | 1 << PTF_COPY_ON_WRITE_BIT;
+                let phys = entry.phys_address();
+                entry.set(phys, flags);
+            }
+
+            let frame = self.pages[&page].clone();
+            child
+                .map_frame(page, frame, flags)
+                .expect("Fresh Address Space Already Mapped");
+        }
+
+        if self.is_active() {
+            x86_64::instructions::tlb::flush_all();
+        }
+        child
+    }
+
+    /// Resolves A Write To A Copy-On-Write Page, Giving This Address Space
+    /// Its Own Copy Unless It Is The Last One Mapping The Frame. Returns
+    /// `false` If `addr` Isn't A Copy-On-Write Page.
+    pub fn copy_on_write(&mut self, addr: VirtAddr) -> bool {
+        let page = addr.align_down(PAGE_SIZE).as_u64();
+        let entry = match self.entry_mut(page) {
+            Some(entry) if entry.get_bit(PTF_COPY_ON_WRITE_BIT) => *entry,
+            _ => return false,
+        };
+
+        let frame = match self.pages.get_mut(&page) {
+            Some(frame) => frame,
+            None => return false,
+        };
+        if Arc::strong_count(frame) > 1 {
+            *frame = Arc::new(Frame::clone(frame));
+        }
+        let phys = frame.phys();
+
+        let flags = entry.value() & !ADDRESS_MASK & !(1 << PTF_COPY_ON_WRITE_BIT);
+        self.entry_mut(page)
+            .expect("Mapped Page Without An Entry")
+            .set(phys, flags | PTFlags::WRITABLE.bits());
+        self.flush(page);
+        true
+    }
+
+    /// Handles A Fault On `addr`. `present` & `write` Come From The Error
+    /// Code: Writes To Shared Pages Are Copied, Swapped Out Pages Are Read
+    /// Back & Untouched Pages Of Stack Or Heap Regions Are Mapped, Anything
+    /// Else Is Returned As An Error.
+    pub fn resolve_fault(
+        &mut self,
+        addr: VirtAddr,
+        present: bool,
+        write: bool,
+    ) -> Result<(), Fault> {
+        if present {
+            if write && self.copy_on_write(addr) {
+                return Ok(());
+            }
+            return Err(Fault::Invalid);
+        }
+        if self.swap_in(addr)? {
+            return Ok(());
+        }
+
+        match self.regions.classify(addr.as_u64()) {
+            Fault::Map(flags) => self
+                .map(addr, flags)
+                .map(|_| ())
+                .map_err(|_| Fault::Invalid),
+            Fault::File(region) => super::mmap::fill(self, &region, addr),
+            fault => Err(fault),
+        }
+    }
+
+    /// Copies Any Kernel Mappings Added Since The Address Space Was Loaded.
+    /// Tables Are Ordered Parents First, So Kernel Tables Adopted By A
+    /// Parent Are Copied Into Its Children In The Same Pass.
+    pub fn sync(&mut self) {
+        for current in 0..self.tables.len() {
+            self.adopt_kernel_tables(current);
+            self.tables[current].sync();
+        }
+    }
+
+    /// A Table Made Private Before The Kernel Had One At The Same Place
+    /// Shadows Nothing, So Once The Kernel Creates One It Starts Shadowing
+    /// That, Otherwise Its Kernel Mappings Would Never Appear.
+    fn adopt_kernel_tables(&mut self, current: usize) {
+        let kernel = match self.tables[current].shadows {
+            Some(kernel) => table_at(kernel),
+            None => return,
+        };
+
+        for index in 0..512 {
+            let entry = kernel[index];
+            if !self.tables[current].is_private(index)
+                || !entry.is_present()
+                || entry.get_bit(PTF_HUGE_PAGE_BIT)
+            {
+                continue;
+            }
+
+            let phys = self.tables[current].table[index].phys_address();
+            if let Some(child) = self
+                .tables
+                .iter_mut()
+                .find(|table| table.phys == phys && table.shadows.is_none())
+            {
+                child.shadows = Some(entry.phys_address());
+            }
+        }
+    }
+
+    /// Refreshes The Kernel Mappings & Loads The Address Space Into CR3.
+    pub fn activate(&mut self) {
+        self.sync();
+        ACTIVE[smp::index()].store(self, Ordering::SeqCst);
+
+        if !self.is_active() {
+            unsafe {
+                Cr3::write(
+                    PhysFrame::containing_address(self.pml4()),
+                    Cr3Flags::empty(),
+                );
+            }
+        }
+    }
+
+    /// The Leaf Entry Of A Page Owned By This Address Space.
+    fn entry_mut(&mut self, page: u64) -> Option<&mut PageTableEntry> {
+        let leaf = self.walk(page, false).ok()??;
+        let table = &mut self.tables[leaf];
+        if table.is_private(p1_index(page)) {
+            Some(&mut table.table[p1_index(page)])
+        } else {
+            None
+        }
+    }
+
+    /// Walks Down To The Page Table Covering `page`, Returning Its Index In
+    /// `tables`. Shared Tables Are Copied & Missing Ones Created If `create`.
+    fn walk(&mut self, page: u64, create: bool) -> Result<Option<usize>, MapError> {
+        let indices = [p4_index(page), p3_index(page), p2_index(page)];
+        let mut current = 0;
+
+        for index in indices {
+            let entry = self.tables[current].table[index];
+            if entry.is_present() && entry.get_bit(PTF_HUGE_PAGE_BIT) {
+                return Err(MapError::HugePage(page));
+            }
+
+            let next = if self.tables[current].is_private(index) {
+                let phys = entry.phys_address();
+                self.tables
+                    .iter()
+                    .position(|table| table.phys == phys)
+                    .expect("Private Entry Without An Owned Table")
+            } else if !create {
+                return Ok(None);
+            } else {
+                let shadows = entry.is_present().then(|| entry.phys_address());
+                self.tables.push(OwnedTable::new(shadows));
+                self.tables.len() - 1
+            };
+
+            let phys = self.tables[next].phys;
+            let parent = &mut self.tables[current];
+            parent.table[index].set(phys, entry.value() & 0xFFF | TABLE_FLAGS);
+            parent.set_private(index, true);
+            current = next;
+        }
+
+        Ok(Some(current))
+    }
+
+    /// Drops `page` From The TLB Of Every CPU That May Have Cached It.
+    fn flush(&self, page: u64) {
+        if self.is_active() {
+            x86_64::instructions::tlb::flush(VirtAddr::new(page));
+        }
+
+        let this = smp::index();
+        let space = self as *const Self as *mut Self;
+        let elsewhere = ACTIVE
+            .iter()
+            .enumerate()
+            .any(|(cpu, active)| cpu != this && active.load(Ordering::SeqCst) == space);
+        if elsewhere {
+            smp::shootdown();
+        }
+    }
+}
+
+impl Default for AddressSpace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        // Never Free The Tables The CPU Is Using. Other CPUs Have Switched
+        // Away By Now, But Mustn't Be Left Pointing At The Freed Space.
+        if self.is_active() {
+            activate_kernel();
+        }
+        let space = self as *mut Self;
+        for active in ACTIVE.iter() {
+            let _ = active.compare_exchange(
+                space,
+                core::ptr::null_mut(),
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            );
+        }
+
+        self.regions.iter().for_each(mmap::unpin);
+
+        let slots = self.swapped.values().map(|swapped| swapped.slot);
+        for slot in slots.chain(self.backing.values().copied()) {
+            swap::free(slot);
+        }
+    }
+}
+
+/// Loads `space` Into CR3, Or The Kernel's Own Tables If It Is Null.
+///
+/// ## Safety
+/// - `space` Must Be Null Or Point To A Live `AddressSpace`.
+pub unsafe fn activate(space: *mut AddressSpace) {
+    match space.as_mut() {
+        Some(space) => space.activate(),
+        None => activate_kernel(),
+    }
+}
+
+pub fn activate_kernel() {
+    ACTIVE[smp::index()].store(core::ptr::null_mut(), Ordering::SeqCst);
+    let kernel = kernel_pml4();
+    if Cr3::read().0.start_address() != kernel {
+        unsafe {
+            Cr3::write(PhysFrame::containing_address(kernel), Cr3Flags::empty());
+        }
+    }
+}
+
+/// Whether The Kernel's Own Tables Map `addr`. Walks Them Directly Rather
+/// Than Through The Page Table Lock, So Page Faults Can Ask.
+pub fn is_kernel_mapped(addr: VirtAddr) -> bool {
+    // Nothing Is Reachable Before Memory Is Set Up.
+    if super::phys_to_virt(PhysAddr::zero()).is_none() {
+        return false;
+    }
+
+    let addr = addr.as_u64();
+    let mut table = table_at(kernel_pml4());
+    for index in [p4_index(addr), p3_index(addr), p2_index(addr)] {
+        let entry = table[index];
+        if !entry.is_present() {
+            return false;
+        }
+        if entry.get_bit(PTF_HUGE_PAGE_BIT) {
+            return true;
+        }
+        table = table_at(entry.phys_address());
+    }
+    table[p1_index(addr)].is_present()
+}
+
+/// Brings The Loaded Address Space Up To Date With The Kernel's Tables.
+pub fn sync_active() {
+    if let Some(space) = unsafe { ACTIVE[smp::index()].load(Ordering::Relaxed).as_mut() } {
+        space.sync();
+    }
+}
+
+fn kernel_pml4() -> PhysAddr {
+    super::kernel_page_table().start_address()
+}
+
+fn table_at(phys: PhysAddr) -> &'static mut PageTable {
+    let virt = super::phys_to_virt(phys).expect("Physical Memory Is Not Mapped");
+    unsafe { &mut *virt.as_mut_ptr() }
+}
+
+fn p4_index(addr: u64) -> usize {
+    ((addr >> 39) & 0x1FF) as usize
+}
+
+fn p3_index(addr: u64) -> usize {
+    ((addr >> 30) & 0x1FF) as usize
+}
+
+fn p2_index(addr: u64) -> usize {
+    ((addr >> 21) & 0x1FF) as usize
+}
+
+fn p1_index(addr: u64) -> usize {
+    ((addr >> 12) & 0x1FF) as usize
+}
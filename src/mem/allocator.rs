@@ -1,27 +1,41 @@
 pub const HEAP_START: usize = 0x_000A_0000_0000;
-/// 64 MB Heap
-pub const HEAP_SIZE: usize = 64 << 20;
+/// 16 MB Mapped At Boot
+pub const HEAP_SIZE: usize = 16 << 20;
+/// The Heap Grows On Demand Up To 1 GB
+pub const HEAP_MAX_SIZE: usize = 1 << 30;
+/// Smallest Amount The Heap Grows By At Once
+const HEAP_GROW_STEP: usize = 1 << 20;
 
 // in src/allocator.rs
 
 #[global_allocator]
+static ALLOCATOR: KernelAllocator = KernelAllocator;
+
 static LINKED_LIST_ALLOCATOR: LockedHeap = LockedHeap::empty();
 
-use core::{alloc::Layout, panic, ptr::NonNull};
+/// The Most Heap Memory Ever In Use At Once.
+static HIGH_WATER: AtomicUsize = AtomicUsize::new(0);
 
-use bit_field::BitField;
-use bootloader::{boot_info::MemoryRegionKind, BootInfo};
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    panic,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use linked_list_allocator::LockedHeap;
+use linked_list_allocator::{Heap, LockedHeap};
 use x86_64::{
-    structures::paging::{mapper::MapToError, FrameAllocator, Mapper, Page, PhysFrame, Size4KiB},
-    PhysAddr, VirtAddr,
+    instructions::interrupts::without_interrupts,
+    structures::paging::{mapper::MapToError, FrameAllocator, Mapper, Page, Size4KiB},
+    VirtAddr,
 };
 
 use x86_64::structures::paging::PageTableFlags as PTFlags;
 
 use crate::sprint;
 
+use super::{address_space, frames, slab};
+
 pub fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
@@ -72,129 +86,142 @@ pub fn init_heap(
     Ok(())
 }
 
-pub(super) fn _malloc(layout: Layout) -> NonNull<u8> {
-    if let Ok(ptr) = LINKED_LIST_ALLOCATOR.lock().allocate_first_fit(layout) {
-        ptr
-    } else {
-        panic!(
-            "Allocation Error: Unable To Allocate {} Bytes (Align: {})",
-            layout.size(),
-            layout.align()
-        );
+/// Wraps The Heap So That Its Lock Is Never Held While Interrupts Are Enabled.
+///
+/// A Thread Preempted While Holding The Heap Lock Would Otherwise Deadlock
+/// Any Interrupt Handler (Or The Scheduler) That Allocates.
+pub struct KernelAllocator;
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    // Kept Out Of Line So Backtraces Skip A Fixed Number Of Frames.
+    #[inline(never)]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        #[cfg(feature = "debug")]
+        let trace = super::tracker::Backtrace::capture();
+        without_interrupts(|| {
+            let ptr = allocate(layout).map_or(ptr::null_mut(), NonNull::as_ptr);
+            #[cfg(feature = "debug")]
+            super::tracker::record_alloc(ptr, layout, trace);
+            ptr
+        })
     }
-}
 
-pub(super) fn _used() -> usize {
-    LINKED_LIST_ALLOCATOR.lock().used()
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Some(ptr) = NonNull::new(ptr) {
+            without_interrupts(|| {
+                #[cfg(feature = "debug")]
+                super::tracker::record_dealloc(ptr.as_ptr());
+                deallocate(ptr, layout)
+            })
+        }
+    }
 }
 
-pub(super) fn _free() -> usize {
-    LINKED_LIST_ALLOCATOR.lock().free()
+/// Serves Small Objects From The Slab Caches & The Rest From The Heap.
+fn allocate(layout: Layout) -> Result<NonNull<u8>, ()> {
+    if slab::is_cached(&layout) {
+        slab::allocate(layout).ok_or(())
+    } else {
+        allocate_large(layout)
+    }
 }
 
-pub(super) fn _dealloc(ptr: NonNull<u8>, layout: Layout) {
-    unsafe {
-        LINKED_LIST_ALLOCATOR.lock().deallocate(ptr, layout);
+/// ## Safety
+/// - `ptr` Must Have Come From [`allocate`] With The Same `layout`.
+unsafe fn deallocate(ptr: NonNull<u8>, layout: Layout) {
+    if slab::is_cached(&layout) {
+        slab::deallocate(ptr, layout)
+    } else {
+        without_interrupts(|| LINKED_LIST_ALLOCATOR.lock().deallocate(ptr, layout))
     }
 }
 
-pub const PHYS_MEMORY_SIZE: usize = 256 << 24;
-pub const PHYS_MEMORY_PAGES: usize = PHYS_MEMORY_SIZE / 4096;
-pub const CHUNK_SIZE: usize = u128::BITS as usize;
-pub static mut FRAME_BITMAP: [u128; PHYS_MEMORY_PAGES / CHUNK_SIZE] =
-    [0; PHYS_MEMORY_PAGES / CHUNK_SIZE];
-pub static mut ALLOC_COUNT: usize = 0;
-pub static mut FREE_COUNT: usize = 0;
-pub struct BitmapAllocator;
-
-impl BitmapAllocator {
-    pub fn free_count() -> usize {
-        unsafe { FREE_COUNT }
-    }
+/// Allocates From The Heap, Growing It First If No Hole Is Large Enough.
+/// The Heap & Page Table Locks Are Only Held With Interrupts Disabled.
+pub(super) fn allocate_large(layout: Layout) -> Result<NonNull<u8>, ()> {
+    without_interrupts(|| {
+        let mut heap = LINKED_LIST_ALLOCATOR.lock();
+        let ptr = heap.allocate_first_fit(layout).or_else(|_| {
+            grow(&mut heap, layout.size() + layout.align())?;
+            heap.allocate_first_fit(layout)
+        })?;
+        HIGH_WATER.fetch_max(heap.used(), Ordering::Relaxed);
+        Ok(ptr)
+    })
+}
 
-    pub fn used_count() -> usize {
-        unsafe { ALLOC_COUNT }
+/// Maps At Least `bytes` More After The Top Of The Heap, Failing Once The
+/// Heap Would Pass `HEAP_MAX_SIZE` Or Physical Memory Runs Out.
+fn grow(heap: &mut Heap, bytes: usize) -> Result<(), ()> {
+    let by = (bytes.max(HEAP_GROW_STEP) + 0xFFF) & !0xFFF;
+    let top = heap.top();
+    if top + by > HEAP_START + HEAP_MAX_SIZE {
+        return Err(());
     }
 
-    fn alloc(frame_no: usize) {
-        //klog!("Mapping Frame {:}\n", frame_no);
-
-        let offset = frame_no / CHUNK_SIZE;
-        let bit = frame_no % CHUNK_SIZE;
-
-        unsafe {
-            if offset >= FRAME_BITMAP.len() {
-                return;
-            }
-            FRAME_BITMAP[offset].set_bit(bit, true);
+    let mut mapped = 0;
+    while mapped < by {
+        let page = VirtAddr::new((top + mapped) as u64);
+        // A Stray Access Past The Top May Already Have Demand Mapped It.
+        if !super::is_mapped(page) {
+            let frame = match frames::allocate() {
+                Some(frame) => frame,
+                None => break,
+            };
+            let flags = PTFlags::PRESENT | PTFlags::WRITABLE | PTFlags::BIT_10;
+            super::map_virt_to_phys(page, frame.start_address(), flags);
         }
+        mapped += 4096;
     }
 
-    fn _free(frame_no: usize) {
-        let offset = frame_no / CHUNK_SIZE;
-        let bit = frame_no % CHUNK_SIZE;
-
-        unsafe {
-            if offset >= FRAME_BITMAP.len() {
-                return;
-            }
-            FRAME_BITMAP[offset].set_bit(bit, false);
-        }
+    if mapped == 0 {
+        return Err(());
     }
 
-    pub fn init(info: &BootInfo) {
-        let regions = info.memory_regions.iter();
-        let usable_regions = regions.filter(|r| r.kind != MemoryRegionKind::Usable);
-        // map each region to its address range
-        let addr_ranges = usable_regions.map(|r| r.start..r.end);
-        // transform to an iterator of frame start addresses
-        let frame_addresses = addr_ranges.flat_map(|r| r.step_by(4096));
-        // create `PhysFrame` types from the start addresses
-        let frames = frame_addresses
-            .map(|addr| PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(addr)));
-
-        let frame_count = frames.clone().count();
-
-        unsafe {
-            ALLOC_COUNT = frame_count;
-            FREE_COUNT = PHYS_MEMORY_PAGES - ALLOC_COUNT;
-        }
+    // New Page Tables May Have Been Added Under A Table The Active Address
+    // Space Has Its Own Copy Of.
+    address_space::sync_active();
+    unsafe { heap.extend(mapped) };
+    Ok(())
+}
 
-        for frame in frames {
-            for offset in 0..(frame.size() / 4096) {
-                Self::alloc((frame.start_address().as_u64() / 4096) as usize + offset as usize);
-            }
-        }
+#[inline(never)]
+pub(super) fn _malloc(layout: Layout) -> NonNull<u8> {
+    #[cfg(feature = "debug")]
+    let trace = super::tracker::Backtrace::capture();
+    if let Ok(ptr) = without_interrupts(|| allocate(layout)) {
+        #[cfg(feature = "debug")]
+        without_interrupts(|| super::tracker::record_alloc(ptr.as_ptr(), layout, trace));
+        ptr
+    } else {
+        panic!(
+            "Allocation Error: Unable To Allocate {} Bytes (Align: {})",
+            layout.size(),
+            layout.align()
+        );
     }
+}
 
-    pub fn next_free() -> Option<PhysFrame> {
-        unsafe {
-            for (index, block) in FRAME_BITMAP.iter().enumerate() {
-                if *block == u128::MAX {
-                    continue;
-                }
-
-                for bit in 0..128 {
-                    if !block.get_bit(bit) {
-                        let frame_no = index * 128 + bit;
-                        let start = frame_no * 4096;
+pub(super) fn _used() -> usize {
+    without_interrupts(|| LINKED_LIST_ALLOCATOR.lock().used())
+}
 
-                        Self::alloc(frame_no);
+pub(super) fn _free() -> usize {
+    without_interrupts(|| LINKED_LIST_ALLOCATOR.lock().free())
+}
 
-                        return Some(PhysFrame::containing_address(PhysAddr::new_truncate(
-                            start as u64,
-                        )));
-                    }
-                }
-            }
+pub(super) fn _size() -> usize {
+    without_interrupts(|| LINKED_LIST_ALLOCATOR.lock().size())
+}
 
-            None
-        }
-    }
+pub(super) fn _high_water() -> usize {
+    HIGH_WATER.load(Ordering::Relaxed)
 }
 
-unsafe impl FrameAllocator<Size4KiB> for BitmapAllocator {
-    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
-        Self::next_free()
-    }
+pub(super) fn _dealloc(ptr: NonNull<u8>, layout: Layout) {
+    without_interrupts(|| unsafe {
+        #[cfg(feature = "debug")]
+        super::tracker::record_dealloc(ptr.as_ptr());
+        deallocate(ptr, layout)
+    })
 }
@@ -0,0 +1,111 @@
+//! Physically Contiguous Buffers For Bus-Master Devices.
+//!
+//! Each Buffer Is Mapped Uncached At `DMA_BASE` Plus Its Physical Address,
+//! So Writes Reach Memory Before The Device Is Told To Look & Reads See
+//! What The Device Wrote, Without Any Cache Flushing.
+use core::ops::{Deref, DerefMut};
+
+use x86_64::{structures::paging::PhysFrame, PhysAddr, VirtAddr};
+
+use super::{
+    address_space,
+    frames::{self, Zone, FRAME_SIZE},
+    PTFlags,
+};
+
+/// Start Of The Uncached Alias Of Physical Memory, PML4 Entry 416.
+const DMA_BASE: u64 = 0xFFFF_D000_0000_0000;
+
+const UNCACHED: PTFlags = PTFlags::PRESENT
+    .union(PTFlags::WRITABLE)
+    .union(PTFlags::NO_CACHE)
+    .union(PTFlags::WRITE_THROUGH);
+
+pub struct DmaBuffer {
+    start: PhysFrame,
+    frames: usize,
+    len: usize,
+}
+
+impl DmaBuffer {
+    /// A Zeroed, Page Aligned Buffer Below 4 GiB.
+    pub fn new(len: usize) -> Option<Self> {
+        Self::with(len, FRAME_SIZE, Zone::Dma32)
+    }
+
+    /// A Zeroed Buffer Starting On An `align` Byte Boundary Inside `zone`.
+    ///
+    /// Returns `None` If No Run Of Free Frames Is Long Enough.
+    pub fn with(len: usize, align: u64, zone: Zone) -> Option<Self> {
+        let count = (len as u64).div_ceil(FRAME_SIZE).max(1) as usize;
+        let start = frames::allocate_aligned(count, align, zone)?;
+
+        // Large Buffers Use 2 MiB Pages Where The Frames Line Up.
+        let size = count * FRAME_SIZE as usize;
+        let phys = start.start_address();
+        super::map_contiguous(size, virt(phys), phys, UNCACHED, true);
+        address_space::sync_active();
+
+        let mut buffer = Self {
+            start,
+            frames: count,
+            len,
+        };
+        buffer.fill(0);
+        Some(buffer)
+    }
+
+    /// The Address To Hand To The Device.
+    pub fn phys(&self) -> PhysAddr {
+        self.start.start_address()
+    }
+
+    pub fn virt(&self) -> VirtAddr {
+        virt(self.phys())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Deref for DmaBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.virt().as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for DmaBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.virt().as_mut_ptr(), self.len) }
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        for frame in 0..self.frames as u64 {
+            super::unmap(self.virt() + frame * FRAME_SIZE);
+        }
+        frames::deallocate_contiguous(self.start, self.frames);
+    }
+}
+
+/// Maps `len` Bytes Of Device Registers At `phys` Into The Uncached Alias,
+/// Returning Their Address. The Mapping Is Never Removed.
+pub fn map_mmio(phys: PhysAddr, len: usize) -> VirtAddr {
+    let start = phys.align_down(FRAME_SIZE);
+    let size = (phys + len as u64).align_up(FRAME_SIZE) - start;
+    super::map_contiguous(size as usize, virt(start), start, UNCACHED, false);
+    address_space::sync_active();
+    virt(phys)
+}
+
+fn virt(phys: PhysAddr) -> VirtAddr {
+    VirtAddr::new(DMA_BASE + phys.as_u64())
+}
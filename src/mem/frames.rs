@@ -1,48 +1,234 @@
-use bootloader::boot_info::*;
+//! The Physical Frame Allocator.
+//!
+//! One Bit Per Frame Up To The Highest Usable Address In The Bootloader's
+//! Memory Map, Set While The Frame Is In Use. The Bitmap Itself Lives In
+//! The First Usable Region Large Enough To Hold It & Is Reached Through The
+//! Physical Memory Mapping, So It Is Ready Before The Heap.
+use bootloader::boot_info::{MemoryRegion, MemoryRegionKind};
+use conquer_once::spin::OnceCell;
 use x86_64::{
-    structures::paging::{FrameAllocator, PhysFrame, Size4KiB},
-    PhysAddr,
+    instructions::interrupts::without_interrupts,
+    structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB},
+    PhysAddr, VirtAddr,
 };
 
-/// A FrameAllocator that returns usable frames from the bootloader's memory map.
-pub struct BootInfoFrameAllocator {
-    memory_map: &'static [MemoryRegion],
+use crate::{locked::Locked, sprint};
+
+pub const FRAME_SIZE: u64 = 4096;
+
+static FRAMES: OnceCell<Locked<FrameBitmap>> = OnceCell::uninit();
+
+/// Limits On Where A Frame May Be Allocated, For Devices That Can't Reach
+/// All Of Physical Memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    /// Below 1 MiB, For Real Mode & ISA DMA.
+    Low,
+    /// Below 4 GiB, For Devices With 32-Bit Addresses.
+    Dma32,
+    /// Anywhere.
+    Normal,
+}
+
+impl Zone {
+    /// The First Physical Address Outside The Zone.
+    pub fn limit(&self) -> u64 {
+        match self {
+            Zone::Low => 1 << 20,
+            Zone::Dma32 => 1 << 32,
+            Zone::Normal => u64::MAX,
+        }
+    }
+}
+
+pub struct FrameBitmap {
+    bitmap: &'static mut [u64],
+    /// Number Of Frames Covered By The Bitmap.
+    frames: usize,
+    free: usize,
+    /// Where The Next Search Starts, Every Frame Below It Is In Use.
     next: usize,
 }
 
-impl BootInfoFrameAllocator {
-    /// Create a FrameAllocator from the passed memory map.
+impl FrameBitmap {
+    /// Builds The Bitmap From `regions`, Storing It In Usable Memory.
     ///
-    /// This function is unsafe because the caller must guarantee that the passed
-    /// memory map is valid. The main requirement is that all frames that are marked
-    /// as `USABLE` in it are really unused.
-    pub unsafe fn init(memory_map: &'static [MemoryRegion]) -> Self {
-        BootInfoFrameAllocator {
-            memory_map,
+    /// ## Safety
+    /// - Every Usable Region Must Really Be Unused & Physical Memory Must Be
+    ///   Mapped At `phys_offset`.
+    unsafe fn new(regions: &[MemoryRegion], phys_offset: VirtAddr) -> Option<Self> {
+        let usable = || {
+            regions
+                .iter()
+                .filter(|region| region.kind == MemoryRegionKind::Usable)
+        };
+
+        let end = usable().map(|region| region.end).max()?;
+        let frames = (end / FRAME_SIZE) as usize;
+        let words = frames.div_ceil(64);
+        let bytes = (words * 8) as u64;
+
+        let storage = usable()
+            .map(|region| (align_up(region.start).max(FRAME_SIZE), region.end))
+            .find(|(start, end)| start + bytes <= *end)
+            .map(|(start, _)| start)?;
+
+        let ptr = (phys_offset + storage).as_mut_ptr::<u64>();
+        let bitmap = core::slice::from_raw_parts_mut(ptr, words);
+        bitmap.fill(u64::MAX);
+
+        let mut allocator = Self {
+            bitmap,
+            frames,
+            free: 0,
             next: 0,
+        };
+
+        for region in usable() {
+            let first = align_up(region.start) / FRAME_SIZE;
+            let last = region.end / FRAME_SIZE;
+            for frame in first..last {
+                allocator.set_used(frame as usize, false);
+            }
+        }
+
+        // Never Hand Out Address 0, Or The Frames Holding The Bitmap.
+        allocator.set_used(0, true);
+        for frame in storage / FRAME_SIZE..align_up(storage + bytes) / FRAME_SIZE {
+            allocator.set_used(frame as usize, true);
+        }
+        Some(allocator)
+    }
+
+    fn is_used(&self, frame: usize) -> bool {
+        frame >= self.frames || self.bitmap[frame / 64] & (1 << (frame % 64)) != 0
+    }
+
+    fn set_used(&mut self, frame: usize, used: bool) {
+        if frame >= self.frames || self.is_used(frame) == used {
+            return;
+        }
+
+        if used {
+            self.bitmap[frame / 64] |= 1 << (frame % 64);
+            self.free -= 1;
+        } else {
+            self.bitmap[frame / 64] &= !(1 << (frame % 64));
+            self.free += 1;
+            self.next = self.next.min(frame);
+        }
+    }
+
+    /// Finds `count` Free Frames In A Row Ending Below `zone`'s Limit, The
+    /// First Being A Multiple Of `align` Frames.
+    fn allocate(&mut self, count: usize, align: usize, zone: Zone) -> Option<PhysFrame> {
+        let limit = self.frames.min((zone.limit() / FRAME_SIZE) as usize);
+        let mut start = self.next.next_multiple_of(align);
+
+        while start + count <= limit {
+            // Skip Full Words Quickly.
+            if start.is_multiple_of(64) && self.bitmap[start / 64] == u64::MAX {
+                start = (start + 64).next_multiple_of(align);
+                continue;
+            }
+
+            match (start..start + count).find(|&frame| self.is_used(frame)) {
+                Some(used) => start = (used + 1).next_multiple_of(align),
+                None => {
+                    for frame in start..start + count {
+                        self.set_used(frame, true);
+                    }
+                    if start == self.next {
+                        self.next = start + count;
+                    }
+                    let addr = PhysAddr::new(start as u64 * FRAME_SIZE);
+                    return Some(PhysFrame::containing_address(addr));
+                }
+            }
         }
+        None
     }
+
+    fn deallocate(&mut self, frame: PhysFrame, count: usize) {
+        let first = (frame.start_address().as_u64() / FRAME_SIZE) as usize;
+        for frame in first..first + count {
+            self.set_used(frame, false);
+        }
+    }
+}
+
+fn align_up(addr: u64) -> u64 {
+    (addr + FRAME_SIZE - 1) & !(FRAME_SIZE - 1)
+}
+
+/// Builds The Frame Bitmap, Must Be Called Before The Heap Is Mapped.
+///
+/// ## Safety
+/// - Every Usable Region Must Really Be Unused & Physical Memory Must Be
+///   Mapped At `phys_offset`.
+pub unsafe fn init(regions: &'static [MemoryRegion], phys_offset: VirtAddr) {
+    FRAMES.init_once(|| {
+        let bitmap = FrameBitmap::new(regions, phys_offset).expect("No Usable Memory");
+        sprint!(
+            "[FRAMES] {} Free Of {} Frames\n",
+            bitmap.free,
+            bitmap.frames
+        );
+        Locked::new(bitmap)
+    });
+}
+
+/// Runs `f` On The Bitmap With Interrupts Disabled.
+fn with<R>(f: impl FnOnce(&mut FrameBitmap) -> R) -> R {
+    let frames = FRAMES.get().expect("Frame Allocator Not Initialized");
+    without_interrupts(|| f(&mut frames.lock()))
+}
+
+pub fn allocate() -> Option<PhysFrame> {
+    allocate_in(Zone::Normal)
 }
 
-impl BootInfoFrameAllocator {
-    /// Returns an iterator over the usable frames specified in the memory map.
-    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> + '_ {
-        // get usable regions from memory map
-        let regions = self.memory_map.iter();
-        let usable_regions = regions.filter(|r| r.kind == MemoryRegionKind::Usable);
-        // map each region to its address range
-        let addr_ranges = usable_regions.map(|r| r.start..r.end);
-        // transform to an iterator of frame start addresses
-        let frame_addresses = addr_ranges.flat_map(|r| r.step_by(4096));
-        // create `PhysFrame` types from the start addresses
-        frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
+pub fn allocate_in(zone: Zone) -> Option<PhysFrame> {
+    allocate_contiguous(1, zone)
+}
+
+/// Allocates `count` Physically Contiguous Frames, Returning The First.
+pub fn allocate_contiguous(count: usize, zone: Zone) -> Option<PhysFrame> {
+    allocate_aligned(count, FRAME_SIZE, zone)
+}
+
+/// Allocates `count` Physically Contiguous Frames Starting On An `align`
+/// Byte Boundary, Which Is Rounded Up To A Whole Number Of Frames.
+pub fn allocate_aligned(count: usize, align: u64, zone: Zone) -> Option<PhysFrame> {
+    let align = (align.div_ceil(FRAME_SIZE) as usize).max(1);
+    with(|frames| frames.allocate(count, align, zone))
+}
+
+pub fn deallocate(frame: PhysFrame) {
+    deallocate_contiguous(frame, 1)
+}
+
+/// Frees `count` Frames Starting At `frame`.
+pub fn deallocate_contiguous(frame: PhysFrame, count: usize) {
+    with(|frames| frames.deallocate(frame, count))
+}
+
+/// Returns `(Free, Total)` Frames.
+pub fn stats() -> (usize, usize) {
+    with(|frames| (frames.free, frames.frames))
+}
+
+/// Hands Frames From The Global Bitmap To `x86_64`'s Mapper.
+pub struct KernelFrameAllocator;
+
+unsafe impl FrameAllocator<Size4KiB> for KernelFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        allocate()
     }
 }
 
-unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
-    fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        let frame = self.usable_frames().nth(self.next);
-        self.next += 1;
-        frame
+impl FrameDeallocator<Size4KiB> for KernelFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        deallocate(frame)
     }
 }
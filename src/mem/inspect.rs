@@ -0,0 +1,266 @@
+//! Shell Commands For Looking At The Memory Map & Page Tables.
+//!
+//! Both Walk The Tables Of The Active Address Space Through The Physical
+//! Memory Mapping, So `pt` Run From A Process Shows That Process's Pages.
+use alloc::{string::String, vec::Vec};
+use bootloader::boot_info::MemoryRegionKind;
+use x86_64::{registers::control::Cr3, PhysAddr, VirtAddr};
+
+use crate::{
+    arch::vmm::{
+        PageTable, PageTableEntry, PTF_ACCESSED_BIT, PTF_COPY_ON_WRITE_BIT, PTF_DIRTY_BIT,
+        PTF_GLOBAL_BIT, PTF_HUGE_PAGE_BIT, PTF_NO_CACHE_BIT, PTF_NO_EXECUTE_BIT,
+        PTF_SHARED_BIT, PTF_USER_BIT, PTF_WRITABLE_BIT, PTF_WRITE_THROUGH_BIT,
+    },
+    csh::{ErrorCode, ExitCode, ShellArgs},
+    println,
+};
+
+/// Bytes Covered By One Entry At Each Level, PML4 First.
+const LEVEL_SIZES: [u64; 4] = [1 << 39, 1 << 30, 1 << 21, 1 << 12];
+const LEVEL_NAMES: [&str; 4] = ["PML4", "PDPT", "PD", "PT"];
+
+/// First Address Of The Upper Half, Past The Non-Canonical Hole.
+const UPPER_HALF: u64 = 0xFFFF_8000_0000_0000;
+
+const FLAG_NAMES: [(usize, &str); 12] = [
+    (PTF_WRITABLE_BIT, "W"),
+    (PTF_USER_BIT, "U"),
+    (PTF_WRITE_THROUGH_BIT, "PWT"),
+    (PTF_NO_CACHE_BIT, "PCD"),
+    (PTF_ACCESSED_BIT, "A"),
+    (PTF_DIRTY_BIT, "D"),
+    (PTF_HUGE_PAGE_BIT, "PS"),
+    (PTF_GLOBAL_BIT, "G"),
+    (PTF_COPY_ON_WRITE_BIT, "COW"),
+    (PTF_SHARED_BIT, "SHR"),
+    (PTF_NO_EXECUTE_BIT, "NX"),
+    (0, "P"),
+];
+
+/// Where A Walk Ended.
+enum Leaf {
+    /// `size` Bytes Mapped To `phys` By `entry`.
+    Mapped {
+        phys: u64,
+        size: u64,
+        entry: PageTableEntry,
+    },
+    /// Nothing Is Mapped In The Aligned `size` Bytes Around The Address.
+    Unmapped { size: u64 },
+}
+
+/// Walks The Active Tables Down To `addr`, Calling `visit` With The Level,
+/// Index & Entry Of Each Step.
+fn walk(addr: u64, mut visit: impl FnMut(usize, usize, &PageTableEntry)) -> Leaf {
+    let mut table = table_at(Cr3::read().0.start_address());
+
+    for (level, size) in LEVEL_SIZES.iter().copied().enumerate() {
+        let index = ((addr >> (39 - 9 * level)) & 0x1FF) as usize;
+        let entry = table[index];
+        visit(level, index, &entry);
+
+        if !entry.is_present() {
+            return Leaf::Unmapped { size };
+        }
+        if level == 3 || (level > 0 && entry.get_bit(PTF_HUGE_PAGE_BIT)) {
+            return Leaf::Mapped {
+                phys: entry.address() + (addr & (size - 1)),
+                size,
+                entry,
+            };
+        }
+        table = table_at(entry.phys_address());
+    }
+    unreachable!()
+}
+
+fn table_at(phys: PhysAddr) -> &'static PageTable {
+    let virt = super::phys_to_virt(phys).expect("Physical Memory Is Not Mapped");
+    unsafe { &*virt.as_ptr() }
+}
+
+/// The Names Of The Flags Set In The Raw Entry `bits`.
+fn flag_names(bits: u64) -> String {
+    let names: Vec<&str> = FLAG_NAMES
+        .iter()
+        .filter(|(bit, _)| bits & 1 << bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    names.join(" ")
+}
+
+/// Parses A Hex Address, With Or Without `0x`.
+fn parse_addr(s: &str) -> Option<u64> {
+    let digits: String = s.trim_start_matches("0x").chars().filter(|c| *c != '_').collect();
+    u64::from_str_radix(&digits, 16).ok()
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 30 && b % (1 << 30) == 0 => alloc::format!("{} GiB", b >> 30),
+        b if b >= 1 << 20 && b % (1 << 20) == 0 => alloc::format!("{} MiB", b >> 20),
+        b => alloc::format!("{} KiB", b >> 10),
+    }
+}
+
+/// Prints The Bootloader's Memory Map & The Total Of Each Kind.
+pub fn csh_memmap(_: ShellArgs) -> ExitCode {
+    let map = match unsafe { super::MEMORY_MAP } {
+        Some(map) => map,
+        None => {
+            println!("No Memory Map...");
+            return ExitCode::Error(ErrorCode::General);
+        }
+    };
+
+    let mut totals: Vec<(MemoryRegionKind, u64)> = Vec::new();
+    for region in map.iter() {
+        let size = region.end - region.start;
+        println!(
+            "[{:#016X}-{:#016X}] {:<20} {}",
+            region.start,
+            region.end,
+            alloc::format!("{:?}", region.kind),
+            format_size(size)
+        );
+
+        match totals.iter_mut().find(|(kind, _)| *kind == region.kind) {
+            Some((_, total)) => *total += size,
+            None => totals.push((region.kind, size)),
+        }
+    }
+
+    println!("=== Totals ===");
+    for (kind, total) in totals {
+        println!("{:<20} {}", alloc::format!("{:?}", kind), format_size(total));
+    }
+    ExitCode::Ok
+}
+
+pub fn csh_pt(args: ShellArgs) -> ExitCode {
+    match args.get(1).map(String::as_str) {
+        Some("--dump") => match (args.get(2).and_then(|s| parse_range(s)), args.len()) {
+            (Some((start, end)), 3) => dump(start, end),
+            _ => {
+                println!("Usage: {} --dump [Start]..[End]", args[0]);
+                ExitCode::Error(ErrorCode::Usage)
+            }
+        },
+        Some(addr) if args.len() == 2 => match parse_addr(addr).map(VirtAddr::try_new) {
+            Some(Ok(addr)) => translate(addr),
+            _ => {
+                println!("Invalid Address: '{}'", addr);
+                ExitCode::Error(ErrorCode::Usage)
+            }
+        },
+        _ => {
+            println!("Usage: {} [Address] | --dump [Start]..[End]", args[0]);
+            ExitCode::Error(ErrorCode::Usage)
+        }
+    }
+}
+
+/// Parses `start..end`, Both Canonical With `start` Below `end`.
+fn parse_range(s: &str) -> Option<(u64, u64)> {
+    let (start, end) = s.split_once("..")?;
+    let start = VirtAddr::try_new(parse_addr(start)?).ok()?.as_u64();
+    let end = parse_addr(end)?;
+    (start < end).then_some((start, end))
+}
+
+/// Prints Each Level's Entry For `addr`.
+fn translate(addr: VirtAddr) -> ExitCode {
+    let leaf = walk(addr.as_u64(), |level, index, entry| {
+        println!(
+            "{:<4} [{:03}] {} {}",
+            LEVEL_NAMES[level],
+            index,
+            entry,
+            flag_names(entry.value())
+        );
+    });
+
+    match leaf {
+        Leaf::Mapped { phys, size, .. } => {
+            println!("{:#x} -> {:#x} ({} Page)", addr, phys, format_size(size));
+        }
+        Leaf::Unmapped { .. } => {
+            println!("{:#x} Is Not Mapped", addr);
+        }
+    }
+    ExitCode::Ok
+}
+
+/// A Run Of Mappings That Are Contiguous In Both Virtual & Physical Memory
+/// With The Same Flags.
+struct Run {
+    virt: u64,
+    phys: u64,
+    size: u64,
+    flags: u64,
+}
+
+impl Run {
+    fn print(&self) {
+        println!(
+            "{:#016x}-{:#016x} -> {:#012x} {:>8} {}",
+            self.virt,
+            self.virt + self.size,
+            self.phys,
+            format_size(self.size),
+            flag_names(self.flags)
+        );
+    }
+}
+
+/// Lists Every Mapping Between `start` & `end`, Joining Neighbouring Pages.
+fn dump(start: u64, end: u64) -> ExitCode {
+    // Accessed & Dirty Differ Between Otherwise Identical Pages.
+    let ignored = 1 << PTF_ACCESSED_BIT | 1 << PTF_DIRTY_BIT | 1 << PTF_HUGE_PAGE_BIT;
+
+    let mut run: Option<Run> = None;
+    let mut addr = start;
+    while addr < end {
+        let leaf = walk(addr, |_, _, _| {});
+        let size = match leaf {
+            Leaf::Mapped { size, .. } | Leaf::Unmapped { size } => size,
+        };
+
+        if let Leaf::Mapped { phys, entry, .. } = leaf {
+            let flags = entry.value() & 0xFFF0_0000_0000_0FFF & !ignored;
+            match run.as_mut() {
+                Some(run)
+                    if run.virt + run.size == addr
+                        && run.phys + run.size == phys
+                        && run.flags == flags =>
+                {
+                    run.size += size - (addr & (size - 1));
+                }
+                _ => {
+                    if let Some(run) = run.take() {
+                        run.print();
+                    }
+                    run = Some(Run {
+                        virt: addr,
+                        phys,
+                        size: size - (addr & (size - 1)),
+                        flags,
+                    });
+                }
+            }
+        }
+
+        // Step To The Next Entry, Jumping The Non-Canonical Hole.
+        addr = match (addr & !(size - 1)).checked_add(size) {
+            Some(next) if next == 1 << 47 => UPPER_HALF,
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    if let Some(run) = run {
+        run.print();
+    }
+    ExitCode::Ok
+}
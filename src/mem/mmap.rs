@@ -0,0 +1,193 @@
+//! Files Mapped Into Address Spaces.
+//!
+//! Mapping A File Only Reserves A `File` Region, Each Page Is Read From The
+//! Mounted Device The First Time It Is Touched. Pages Of Files On Writable
+//! File Systems That The CPU Has Marked Dirty Are Written Back By `msync` &
+//! `unmap`, & By `msync_all` When Their Process Exits.
+//!
+//! While Any Address Space Maps A File Its Extent Is Pinned, So The File
+//! System Refuses To Delete Or Move It Under The Mapping.
+use core::ops::Range;
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use spin::Mutex;
+use x86_64::{instructions::interrupts::without_interrupts, VirtAddr};
+
+use crate::{
+    ata::BLOCK_SIZE,
+    device::{self, BlockAddr},
+    vfs::drivers::{FileExtent, VirtFileSystem},
+};
+
+use super::{
+    address_space::{AddressSpace, Frame, PAGE_SIZE},
+    regions::{Fault, Region, RegionKind},
+    PTFlags,
+};
+
+/// Number Of Regions Mapping Each Extent, By Its First Block.
+static PINNED: Mutex<BTreeMap<BlockAddr, usize>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmapError {
+    /// The File Doesn't Exist Or Can't Be Mapped By Its File System.
+    NotFound,
+    /// The Range Overlaps A Region Of The Address Space.
+    AddressInUse(u64),
+    /// A Writable Mapping Of A File On A Read-Only File System.
+    ReadOnly,
+    /// No File Is Mapped At The Address.
+    NotMapped(u64),
+    /// Writing The Block Back Failed.
+    Io(BlockAddr),
+}
+
+/// Maps `filename` From `fs` At `addr` With `flags`, Returning The Length
+/// Of The Mapping. Nothing Is Read Until A Page Is Touched.
+pub fn map(
+    space: &mut AddressSpace,
+    fs: &dyn VirtFileSystem,
+    filename: &str,
+    addr: VirtAddr,
+    flags: PTFlags,
+) -> Result<usize, MmapError> {
+    let extent = fs.locate(filename).ok_or(MmapError::NotFound)?;
+    if flags.contains(PTFlags::WRITABLE) && !extent.writable {
+        return Err(MmapError::ReadOnly);
+    }
+
+    let start = addr.align_down(PAGE_SIZE).as_u64();
+    let len = (extent.size as u64).div_ceil(PAGE_SIZE).max(1) * PAGE_SIZE;
+    let region = Region::new(start, start + len, RegionKind::File(extent), flags);
+    if !space.add_region(region) {
+        return Err(MmapError::AddressInUse(start));
+    }
+    Ok(len as usize)
+}
+
+/// Reads The Page Holding `addr` From The File Behind `region`. Pages Are
+/// Mapped Shared, So Forked Children Write To The Same Copy.
+pub(super) fn fill(space: &mut AddressSpace, region: &Region, addr: VirtAddr) -> Result<(), Fault> {
+    let extent = file_extent(region).ok_or(Fault::Invalid)?;
+    let page = addr.align_down(PAGE_SIZE);
+    let offset = page.as_u64() - region.start;
+
+    let mut frame = Frame::zeroed();
+    for (index, chunk) in frame.data_mut().chunks_mut(BLOCK_SIZE).enumerate() {
+        let position = offset as usize + index * BLOCK_SIZE;
+        if position >= extent.size {
+            break;
+        }
+
+        let block = device::read_block(block_at(&extent, position)).map_err(|_| Fault::Invalid)?;
+        chunk.copy_from_slice(block.data());
+    }
+
+    space
+        .map_shared(page, Arc::new(frame), region.flags)
+        .map_err(|_| Fault::Invalid)
+}
+
+/// Writes Back Every Dirty Page Of The File Mapped Over `addr`.
+pub fn msync(space: &mut AddressSpace, addr: VirtAddr) -> Result<(), MmapError> {
+    let region = file_region(space, addr)?;
+    let extent = file_extent(&region).ok_or(MmapError::NotMapped(addr.as_u64()))?;
+    if !extent.writable || !region.flags.contains(PTFlags::WRITABLE) {
+        return Ok(());
+    }
+
+    for page in (region.start..region.end).step_by(PAGE_SIZE as usize) {
+        let page = VirtAddr::new(page);
+        if !space.is_dirty(page) {
+            continue;
+        }
+
+        let data = space.page(page).expect("Dirty Page Not Mapped");
+        let offset = (page.as_u64() - region.start) as usize;
+        for (index, chunk) in data.chunks(BLOCK_SIZE).enumerate() {
+            let position = offset + index * BLOCK_SIZE;
+            if position >= extent.size {
+                break;
+            }
+
+            let block = block_at(&extent, position);
+            device::write(block, chunk).map_err(|_| MmapError::Io(block))?;
+        }
+        // Only Now, So A Failed Write Is Retried By The Next Sync.
+        space.take_dirty(page);
+    }
+    Ok(())
+}
+
+/// Writes Back Every File Mapped Into `space`.
+pub fn msync_all(space: &mut AddressSpace) -> Result<(), MmapError> {
+    let files: Vec<u64> = space
+        .regions()
+        .iter()
+        .filter(|region| file_extent(region).is_some())
+        .map(|region| region.start)
+        .collect();
+
+    for start in files {
+        msync(space, VirtAddr::new(start))?;
+    }
+    Ok(())
+}
+
+/// Writes Back & Removes The File Mapped Over `addr`.
+pub fn unmap(space: &mut AddressSpace, addr: VirtAddr) -> Result<(), MmapError> {
+    msync(space, addr)?;
+    let region = file_region(space, addr)?;
+    for page in (region.start..region.end).step_by(PAGE_SIZE as usize) {
+        space.unmap(VirtAddr::new(page));
+    }
+    space.remove_region(region.start);
+    Ok(())
+}
+
+/// Records Another Mapping Of The File Behind `region`, If It Is One.
+pub(super) fn pin(region: &Region) {
+    if let Some(extent) = file_extent(region) {
+        without_interrupts(|| *PINNED.lock().entry(extent.start).or_insert(0) += 1);
+    }
+}
+
+/// Undoes [`pin`] Once `region` Is Gone.
+pub(super) fn unpin(region: &Region) {
+    if let Some(extent) = file_extent(region) {
+        without_interrupts(|| {
+            let mut pinned = PINNED.lock();
+            if let Some(count) = pinned.get_mut(&extent.start) {
+                *count -= 1;
+                if *count == 0 {
+                    pinned.remove(&extent.start);
+                }
+            }
+        });
+    }
+}
+
+/// Whether A Mapped File Starts In `blocks`, In Which Case Its Blocks Must
+/// Stay Where They Are.
+pub fn is_pinned(blocks: Range<BlockAddr>) -> bool {
+    without_interrupts(|| PINNED.lock().range(blocks).next().is_some())
+}
+
+fn file_region(space: &AddressSpace, addr: VirtAddr) -> Result<Region, MmapError> {
+    match space.regions().find(addr.as_u64()) {
+        Some(region) if file_extent(region).is_some() => Ok(*region),
+        _ => Err(MmapError::NotMapped(addr.as_u64())),
+    }
+}
+
+fn file_extent(region: &Region) -> Option<FileExtent> {
+    match region.kind {
+        RegionKind::File(extent) => Some(extent),
+        _ => None,
+    }
+}
+
+/// The Block Holding Byte `position` Of The File.
+fn block_at(extent: &FileExtent, position: usize) -> BlockAddr {
+    extent.start + (position / BLOCK_SIZE) as BlockAddr
+}
@@ -0,0 +1,203 @@
+//! Tables Of The Virtual Memory Regions Page Faults Are Allowed In.
+use alloc::collections::BTreeMap;
+use conquer_once::spin::OnceCell;
+use x86_64::{
+    instructions::interrupts::without_interrupts, structures::paging::PageTableFlags as PTFlags,
+};
+
+use crate::{locked::Locked, vfs::drivers::FileExtent};
+
+use super::allocator::{HEAP_MAX_SIZE, HEAP_START};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    /// Pages Are Mapped On First Touch As The Heap Grows.
+    Heap,
+    /// Pages Are Mapped On First Touch As The Stack Grows Down.
+    Stack,
+    /// Never Mapped, Touching It Means A Stack Overflowed.
+    Guard,
+    /// Shared Memory, Every Page Is Mapped Up Front.
+    Shared,
+    /// A Mapped File, Pages Are Read From The Device On First Touch.
+    File(FileExtent),
+    /// Kernel Memory, Never Given To User Programs.
+    Reserved,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: u64,
+    pub end: u64,
+    pub kind: RegionKind,
+    /// The Flags Pages Are Mapped With.
+    pub flags: PTFlags,
+}
+
+impl Region {
+    pub fn new(start: u64, end: u64, kind: RegionKind, flags: PTFlags) -> Self {
+        Self {
+            start,
+            end,
+            kind,
+            flags,
+        }
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        (self.start..self.end).contains(&addr)
+    }
+
+    /// Whether A Fault In The Region Is Resolved By Mapping A Fresh Page.
+    pub fn is_demand_mapped(&self) -> bool {
+        matches!(self.kind, RegionKind::Heap | RegionKind::Stack)
+    }
+}
+
+/// The Outcome Of A Page Fault, Decided By Where It Happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Map A Fresh Page With The Given Flags.
+    Map(PTFlags),
+    /// Fill A Page Of The Mapped File Region.
+    File(Region),
+    /// A Stack Ran Into Its Guard Page.
+    Guard,
+    /// Outside Every Region, Or An Access The Page Doesn't Allow.
+    Invalid,
+}
+
+/// Non-Overlapping Regions Keyed By Their Start Address.
+#[derive(Debug, Clone, Default)]
+pub struct RegionTable {
+    regions: BTreeMap<u64, Region>,
+}
+
+impl RegionTable {
+    pub const fn new() -> Self {
+        Self {
+            regions: BTreeMap::new(),
+        }
+    }
+
+    /// Adds `region`, Returning `false` If It Overlaps An Existing One.
+    pub fn insert(&mut self, region: Region) -> bool {
+        let overlaps = self
+            .regions
+            .values()
+            .any(|other| region.start < other.end && other.start < region.end);
+        if overlaps || region.start >= region.end {
+            return false;
+        }
+
+        self.regions.insert(region.start, region);
+        true
+    }
+
+    pub fn remove(&mut self, start: u64) -> Option<Region> {
+        self.regions.remove(&start)
+    }
+
+    pub fn find(&self, addr: u64) -> Option<&Region> {
+        self.regions
+            .range(..=addr)
+            .next_back()
+            .map(|(_, region)| region)
+            .filter(|region| region.contains(addr))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Region> {
+        self.regions.values()
+    }
+
+    /// Decides What To Do About A Fault On The Unmapped Address `addr`.
+    pub fn classify(&self, addr: u64) -> Fault {
+        match self.find(addr) {
+            Some(region) if region.is_demand_mapped() => Fault::Map(region.flags),
+            Some(region) => match region.kind {
+                RegionKind::File(_) => Fault::File(*region),
+                RegionKind::Guard => Fault::Guard,
+                _ => Fault::Invalid,
+            },
+            None => Fault::Invalid,
+        }
+    }
+}
+
+/// Regions Of The Kernel's Own Address Space.
+static KERNEL_REGIONS: OnceCell<Locked<RegionTable>> = OnceCell::uninit();
+
+/// Low Memory Is Identity Mapped For The AP Trampoline.
+const LOW_MEMORY_END: u64 = 0x10_0000;
+
+extern "C" {
+    // Provided By The Linker.
+    static __executable_start: u8;
+    static _end: u8;
+}
+
+/// Registers The Kernel Image, The Heap, Low Memory & The Guard Page
+/// Catching Null Pointers.
+pub fn init() {
+    KERNEL_REGIONS.init_once(|| {
+        let image_start = core::ptr::addr_of!(__executable_start) as u64;
+        let image_end = core::ptr::addr_of!(_end) as u64;
+
+        let mut regions = RegionTable::new();
+        regions.insert(Region::new(0, 0x1000, RegionKind::Guard, PTFlags::empty()));
+        regions.insert(Region::new(
+            0x1000,
+            LOW_MEMORY_END,
+            RegionKind::Reserved,
+            PTFlags::empty(),
+        ));
+        regions.insert(Region::new(
+            image_start & !0xFFF,
+            (image_end + 0xFFF) & !0xFFF,
+            RegionKind::Reserved,
+            PTFlags::empty(),
+        ));
+        regions.insert(Region::new(
+            HEAP_START as u64,
+            (HEAP_START + HEAP_MAX_SIZE) as u64,
+            RegionKind::Heap,
+            PTFlags::PRESENT | PTFlags::WRITABLE,
+        ));
+        Locked::new(regions)
+    });
+}
+
+/// Runs `f` On The Kernel's Region Table.
+///
+/// ## Panics
+/// - Panics If `init` Has Not Been Called.
+pub fn with_kernel<R>(f: impl FnOnce(&mut RegionTable) -> R) -> R {
+    let regions = KERNEL_REGIONS
+        .get()
+        .expect("Kernel Regions Not Initialized");
+    without_interrupts(|| f(&mut regions.lock()))
+}
+
+/// Whether `start..end` Overlaps Memory The Kernel Uses Or May Map Later,
+/// Which User Mappings Must Stay Clear Of. Everything Is Treated As Kernel
+/// Memory Before `init`.
+pub fn overlaps_kernel(start: u64, end: u64) -> bool {
+    match KERNEL_REGIONS.get() {
+        Some(regions) => without_interrupts(|| {
+            regions
+                .lock()
+                .iter()
+                .any(|region| start < region.end && region.start < end)
+        }),
+        None => true,
+    }
+}
+
+/// Classifies A Kernel Mode Fault On `addr`, Without Blocking If The Table
+/// Is Already In Use.
+pub fn classify_kernel(addr: u64) -> Fault {
+    match KERNEL_REGIONS.get().and_then(|regions| regions.try_lock()) {
+        Some(regions) => regions.classify(addr),
+        None => Fault::Invalid,
+    }
+}
@@ -0,0 +1,145 @@
+//! Memory Shared Between Address Spaces.
+//!
+//! A Region Owns Its Frames Through The Same `Arc<Frame>`s Address Spaces
+//! Map, So Frames Are Freed Once The Region & Every Mapping Of It Are Gone.
+//! Each Mapping Chooses Its Own Flags, A Client Can Map Read-Only What Its
+//! Producer Maps Writable.
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use conquer_once::spin::OnceCell;
+use x86_64::{instructions::interrupts::without_interrupts, VirtAddr};
+
+use crate::locked::Locked;
+
+use super::{
+    address_space::{AddressSpace, Frame, MapError, PAGE_SIZE},
+    regions::{Region, RegionKind},
+    PTFlags,
+};
+
+static NAMED: OnceCell<Locked<BTreeMap<String, Arc<SharedRegion>>>> = OnceCell::uninit();
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShmError {
+    /// A Region With The Name Already Exists.
+    Exists(String),
+    /// The Range Overlaps A Region Of The Address Space.
+    AddressInUse(u64),
+    Map(MapError),
+}
+
+pub struct SharedRegion {
+    name: Option<String>,
+    frames: Vec<Arc<Frame>>,
+}
+
+impl SharedRegion {
+    /// Creates An Anonymous Region Of At Least `size` Zeroed Bytes.
+    pub fn new(size: usize) -> Arc<Self> {
+        Arc::new(Self::with_name(None, size))
+    }
+
+    fn with_name(name: Option<String>, size: usize) -> Self {
+        let pages = (size as u64).div_ceil(PAGE_SIZE).max(1);
+        Self {
+            name,
+            frames: (0..pages).map(|_| Arc::new(Frame::zeroed())).collect(),
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn size(&self) -> usize {
+        self.frames.len() * PAGE_SIZE as usize
+    }
+
+    /// Number Of Address Space Mappings Of The Region.
+    pub fn mappings(&self) -> usize {
+        Arc::strong_count(&self.frames[0]) - 1
+    }
+
+    /// Maps The Whole Region At `addr` In `space` With `flags`.
+    pub fn map(
+        &self,
+        space: &mut AddressSpace,
+        addr: VirtAddr,
+        flags: PTFlags,
+    ) -> Result<(), ShmError> {
+        let start = addr.align_down(PAGE_SIZE).as_u64();
+        let end = start + self.size() as u64;
+        let region = Region::new(start, end, RegionKind::Shared, flags);
+        if !space.add_region(region) {
+            return Err(ShmError::AddressInUse(start));
+        }
+
+        for (index, frame) in self.frames.iter().enumerate() {
+            let page = VirtAddr::new(start + index as u64 * PAGE_SIZE);
+            if let Err(error) = space.map_shared(page, frame.clone(), flags) {
+                for mapped in 0..index as u64 {
+                    space.unmap(VirtAddr::new(start + mapped * PAGE_SIZE));
+                }
+                space.remove_region(start);
+                return Err(ShmError::Map(error));
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes The Mapping Made At `addr` By [`SharedRegion::map`].
+    pub fn unmap(&self, space: &mut AddressSpace, addr: VirtAddr) {
+        let start = addr.align_down(PAGE_SIZE).as_u64();
+        for index in 0..self.frames.len() as u64 {
+            space.unmap(VirtAddr::new(start + index * PAGE_SIZE));
+        }
+        space.remove_region(start);
+    }
+
+    /// Maps The Region Into The Kernel's Tables At `virt`, Like
+    /// [`super::map_virt_to_phys`]. The Caller Must Keep The Region Alive
+    /// Until [`SharedRegion::unmap_kernel`].
+    pub fn map_kernel(&self, virt: VirtAddr, flags: PTFlags) {
+        for (index, frame) in self.frames.iter().enumerate() {
+            super::map_virt_to_phys(virt + index as u64 * PAGE_SIZE, frame.phys(), flags);
+        }
+        super::address_space::sync_active();
+    }
+
+    pub fn unmap_kernel(&self, virt: VirtAddr) {
+        for index in 0..self.frames.len() as u64 {
+            super::unmap(virt + index * PAGE_SIZE);
+        }
+    }
+}
+
+fn with_named<R>(f: impl FnOnce(&mut BTreeMap<String, Arc<SharedRegion>>) -> R) -> R {
+    let named = NAMED.get_or_init(|| Locked::new(BTreeMap::new()));
+    without_interrupts(|| f(&mut named.lock()))
+}
+
+/// Creates A Region Other Processes Can Find By `name`.
+pub fn create(name: &str, size: usize) -> Result<Arc<SharedRegion>, ShmError> {
+    with_named(|named| {
+        if named.contains_key(name) {
+            return Err(ShmError::Exists(name.to_string()));
+        }
+        let region = Arc::new(SharedRegion::with_name(Some(name.to_string()), size));
+        named.insert(name.to_string(), region.clone());
+        Ok(region)
+    })
+}
+
+pub fn open(name: &str) -> Option<Arc<SharedRegion>> {
+    with_named(|named| named.get(name).cloned())
+}
+
+/// Forgets `name`. Existing Mappings Stay Valid & The Frames Are Freed Once
+/// The Last One Is Gone.
+pub fn remove(name: &str) -> Option<Arc<SharedRegion>> {
+    with_named(|named| named.remove(name))
+}
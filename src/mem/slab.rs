@@ -0,0 +1,172 @@
+//! Size-Class Caches Layered Under The Kernel Heap.
+//!
+//! Small Allocations Are Rounded Up To The Next Size Class & Served From A
+//! Free List Of Equal Sized Objects, Carved Out Of Slabs Taken From The
+//! Linked List Heap. Freed Objects Go Back On Their Cache's Free List, So
+//! Frequent Small Allocations Never Walk (Or Fragment) The Heap's Hole List.
+use core::{alloc::Layout, ptr::NonNull};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// Largest Object Served From A Cache, Anything Bigger Goes To The Heap.
+pub const MAX_SIZE: usize = 4096;
+
+/// Every Slab Is Carved Into At Least This Many Objects.
+const MIN_OBJECTS: usize = 8;
+
+pub const CACHE_COUNT: usize = 9;
+
+static CACHES: [Mutex<SlabCache>; CACHE_COUNT] = [
+    Mutex::new(SlabCache::new(16)),
+    Mutex::new(SlabCache::new(32)),
+    Mutex::new(SlabCache::new(64)),
+    Mutex::new(SlabCache::new(128)),
+    Mutex::new(SlabCache::new(256)),
+    Mutex::new(SlabCache::new(512)),
+    Mutex::new(SlabCache::new(1024)),
+    Mutex::new(SlabCache::new(2048)),
+    Mutex::new(SlabCache::new(4096)),
+];
+
+/// A Free Object, The Link Is Stored In The Object Itself.
+struct FreeObject {
+    next: Option<NonNull<FreeObject>>,
+}
+
+struct SlabCache {
+    size: usize,
+    free: Option<NonNull<FreeObject>>,
+    slabs: usize,
+    used: usize,
+    total: usize,
+}
+
+// The Free List Only Points Into Slabs Owned By The Cache.
+unsafe impl Send for SlabCache {}
+
+impl SlabCache {
+    const fn new(size: usize) -> Self {
+        Self {
+            size,
+            free: None,
+            slabs: 0,
+            used: 0,
+            total: 0,
+        }
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            size: self.size,
+            slabs: self.slabs,
+            used: self.used,
+            total: self.total,
+        }
+    }
+
+    fn slab_size(&self) -> usize {
+        (self.size * MIN_OBJECTS).max(MAX_SIZE)
+    }
+
+    fn allocate(&mut self) -> Option<NonNull<u8>> {
+        if self.free.is_none() {
+            self.refill()?;
+        }
+
+        let object = self.free?;
+        self.free = unsafe { object.as_ref().next };
+        self.used += 1;
+        Some(object.cast())
+    }
+
+    /// ## Safety
+    /// - `ptr` Must Have Come From This Cache & Not Be Freed Already.
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>) {
+        let mut object = ptr.cast::<FreeObject>();
+        object.as_mut().next = self.free;
+        self.free = Some(object);
+        self.used -= 1;
+    }
+
+    /// Takes A New Slab From The Heap & Threads Its Objects Onto The Free
+    /// List. Slabs Are Page Aligned, So Every Object Is Aligned To Its Size.
+    fn refill(&mut self) -> Option<()> {
+        let slab_size = self.slab_size();
+        let layout = Layout::from_size_align(slab_size, MAX_SIZE).ok()?;
+        let slab = super::allocator::allocate_large(layout).ok()?;
+
+        let count = slab_size / self.size;
+        for index in (0..count).rev() {
+            let mut object = unsafe {
+                NonNull::new_unchecked(slab.as_ptr().add(index * self.size)).cast::<FreeObject>()
+            };
+            unsafe { object.as_mut().next = self.free };
+            self.free = Some(object);
+        }
+
+        self.slabs += 1;
+        self.total += count;
+        Some(())
+    }
+}
+
+/// A Snapshot Of One Cache's Usage.
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    /// Object Size In Bytes.
+    pub size: usize,
+    pub slabs: usize,
+    /// Objects Handed Out.
+    pub used: usize,
+    /// Objects Across All Slabs.
+    pub total: usize,
+}
+
+/// The Cache Serving `layout`, If It Is Small Enough For One.
+fn cache_index(layout: &Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align()).max(16);
+    if size > MAX_SIZE {
+        return None;
+    }
+    Some((size.next_power_of_two().trailing_zeros() - 4) as usize)
+}
+
+/// Allocates `layout` From Its Size Class, Taking A New Slab From The Heap
+/// When The Cache Is Empty.
+///
+/// Returns `None` If `layout` Is Too Large For Any Cache, Or The Heap Could
+/// Not Provide A New Slab. Must Be Called With Interrupts Disabled.
+pub(super) fn allocate(layout: Layout) -> Option<NonNull<u8>> {
+    let index = cache_index(&layout)?;
+    CACHES[index].lock().allocate()
+}
+
+/// Whether `layout` Is Served By A Cache Rather Than The Heap.
+pub(super) fn is_cached(layout: &Layout) -> bool {
+    cache_index(layout).is_some()
+}
+
+/// Returns `ptr` To Its Cache, Must Be Called With Interrupts Disabled.
+///
+/// ## Safety
+/// - `ptr` Must Have Been Allocated By [`allocate`] With The Same `layout`.
+pub(super) unsafe fn deallocate(ptr: NonNull<u8>, layout: Layout) {
+    if let Some(index) = cache_index(&layout) {
+        CACHES[index].lock().deallocate(ptr);
+    }
+}
+
+pub fn stats() -> [CacheStats; CACHE_COUNT] {
+    let mut stats = [CacheStats {
+        size: 0,
+        slabs: 0,
+        used: 0,
+        total: 0,
+    }; CACHE_COUNT];
+
+    for (stats, cache) in stats.iter_mut().zip(CACHES.iter()) {
+        *stats = without_interrupts(|| cache.lock().stats());
+    }
+    stats
+}
@@ -0,0 +1,266 @@
+//! Swap Space For Cold User Pages.
+//!
+//! The Swap Area Is A Range Of Blocks On An ATA Drive, Split Into Page Sized
+//! Slots. Address Spaces Choose Pages To Evict With A Clock Sweep Over The
+//! Accessed Bits (See `AddressSpace::swap_out`) & Read Them Back From The
+//! Page Fault Handler. Swap I/O Bypasses The ATA Block Cache, Which Would
+//! Otherwise Keep Every Swapped Page In The Heap.
+//!
+//! Once Swap Is Enabled A Kernel Thread Watches The Free Frames & Sweeps
+//! Every Process's Pages When They Run Low, So Frames The Kernel Needs Are
+//! Freed Up Too, Not Only Those Of The Process That Faulted.
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use alloc::{vec, vec::Vec};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{
+    ata::{self, BLOCK_SIZE},
+    csh::{ErrorCode, ExitCode, ShellArgs},
+    device::{self, BlockAddr, BlockDeviceIO, Device},
+    println, process, task,
+};
+
+use super::{address_space::PAGE_SIZE, frames};
+
+const BLOCKS_PER_SLOT: u32 = (PAGE_SIZE as usize / BLOCK_SIZE) as u32;
+
+/// The Drive The Kernel Is Booted From, `(Bus, Drive)`.
+const BOOT_DRIVE: (u8, u8) = (0, 0);
+
+/// The Swapper Starts Evicting Below `LOW_FREE_FRAMES` Free Frames & Stops
+/// Once `HIGH_FREE_FRAMES` Are Free.
+const LOW_FREE_FRAMES: usize = 256;
+const HIGH_FREE_FRAMES: usize = 512;
+const SWAPPER_INTERVAL_MS: u64 = 100;
+const SWAPPER_BATCH: usize = 16;
+
+static SWAP: Mutex<Option<SwapArea>> = Mutex::new(None);
+static SWAPPER_STARTED: AtomicBool = AtomicBool::new(false);
+/// The Process The Next Sweep Starts With.
+static HAND: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapError {
+    /// Only ATA Drives Can Hold Swap.
+    Unsupported,
+    /// The Area Is Too Small To Hold A Single Page.
+    TooSmall,
+    /// The Blocks Don't Fit On The Drive.
+    OutOfRange,
+    /// The Drive Holds The Mounted File System.
+    Mounted,
+    /// The Drive Holds The Kernel.
+    BootDrive,
+    /// Pages Are Still Swapped Out To The Current Area.
+    InUse,
+}
+
+struct SwapArea {
+    bus: u8,
+    drive: u8,
+    start: BlockAddr,
+    /// One Bit Per Slot, Set While The Slot Holds A Page.
+    bitmap: Vec<u64>,
+    slots: usize,
+    used: usize,
+}
+
+impl SwapArea {
+    fn allocate(&mut self) -> Option<u32> {
+        let slot = (0..self.slots).find(|&slot| self.bitmap[slot / 64] & 1 << (slot % 64) == 0)?;
+        self.bitmap[slot / 64] |= 1 << (slot % 64);
+        self.used += 1;
+        Some(slot as u32)
+    }
+
+    fn free(&mut self, slot: u32) {
+        let slot = slot as usize;
+        if self.bitmap[slot / 64] & 1 << (slot % 64) != 0 {
+            self.bitmap[slot / 64] &= !(1 << (slot % 64));
+            self.used -= 1;
+        }
+    }
+
+    fn block(&self, slot: u32) -> BlockAddr {
+        self.start + slot * BLOCKS_PER_SLOT
+    }
+}
+
+/// Swaps To `blocks` Of `device`, Replacing The Previous Swap Area If No
+/// Pages Are Swapped Out To It. Returns The Number Of Slots. The Boot
+/// Drive & The Mounted Drive Are Refused, Swap Would Overwrite Them.
+pub fn enable(device: Device, blocks: core::ops::Range<BlockAddr>) -> Result<usize, SwapError> {
+    let (bus, drive) = match device {
+        Device::Ata(bus, drive) => (bus, drive),
+        _ => return Err(SwapError::Unsupported),
+    };
+    if (bus, drive) == BOOT_DRIVE {
+        return Err(SwapError::BootDrive);
+    }
+    if device::is_mounted_ata(bus, drive) {
+        return Err(SwapError::Mounted);
+    }
+    match device.block_count() {
+        Ok(count) if blocks.end as usize <= count => {}
+        _ => return Err(SwapError::OutOfRange),
+    }
+
+    let slots = (blocks.len() as u32 / BLOCKS_PER_SLOT) as usize;
+    if slots == 0 {
+        return Err(SwapError::TooSmall);
+    }
+
+    let area = SwapArea {
+        bus,
+        drive,
+        start: blocks.start,
+        bitmap: vec![0; slots.div_ceil(64)],
+        slots,
+        used: 0,
+    };
+    without_interrupts(|| {
+        let mut swap = SWAP.lock();
+        if swap.as_ref().is_some_and(|area| area.used > 0) {
+            return Err(SwapError::InUse);
+        }
+        *swap = Some(area);
+        Ok(())
+    })?;
+
+    if !SWAPPER_STARTED.swap(true, Ordering::SeqCst) {
+        drop(task::spawn("swapper", swapper));
+    }
+    Ok(slots)
+}
+
+/// Evicts Up To `count` Cold Pages From The Address Spaces Of All
+/// Processes, Returning How Many Were Evicted. Each Sweep Starts One
+/// Process Further Along, & Spaces Locked By Someone Else Are Skipped.
+pub fn reclaim(count: usize) -> usize {
+    if !is_enabled() {
+        return 0;
+    }
+
+    let spaces = process::address_spaces();
+    if spaces.is_empty() {
+        return 0;
+    }
+    let first = HAND.fetch_add(1, Ordering::Relaxed) % spaces.len();
+
+    let mut evicted = 0;
+    for space in spaces.iter().cycle().skip(first).take(spaces.len()) {
+        if evicted == count {
+            break;
+        }
+        if let Some(mut space) = space.try_lock() {
+            evicted += space.swap_out(count - evicted);
+        }
+    }
+    evicted
+}
+
+/// Keeps At Least `LOW_FREE_FRAMES` Frames Free For The Kernel & New
+/// Pages By Sweeping Every Process Whenever Memory Runs Low.
+fn swapper() {
+    loop {
+        task::sleep(SWAPPER_INTERVAL_MS);
+        if frames::stats().0 >= LOW_FREE_FRAMES {
+            continue;
+        }
+        while frames::stats().0 < HIGH_FREE_FRAMES {
+            if reclaim(SWAPPER_BATCH) == 0 {
+                break;
+            }
+        }
+    }
+}
+
+pub fn is_enabled() -> bool {
+    without_interrupts(|| SWAP.lock().is_some())
+}
+
+/// Returns `(Used, Total)` Slots.
+pub fn stats() -> (usize, usize) {
+    without_interrupts(|| match SWAP.lock().as_ref() {
+        Some(area) => (area.used, area.slots),
+        None => (0, 0),
+    })
+}
+
+/// Reserves A Slot, `None` If Swap Is Off Or Full.
+pub(super) fn allocate() -> Option<u32> {
+    without_interrupts(|| SWAP.lock().as_mut()?.allocate())
+}
+
+pub(super) fn free(slot: u32) {
+    without_interrupts(|| {
+        if let Some(area) = SWAP.lock().as_mut() {
+            area.free(slot);
+        }
+    })
+}
+
+/// Writes A Page To `slot`.
+pub(super) fn write(slot: u32, data: &[u8; PAGE_SIZE as usize]) -> Result<(), ()> {
+    let (bus, drive, block) = location(slot)?;
+    for (index, chunk) in data.chunks(BLOCK_SIZE).enumerate() {
+        ata::write_block_uncached(bus, drive, block + index as u32, chunk)?;
+    }
+    Ok(())
+}
+
+/// Reads The Page In `slot` Into `data`.
+pub(super) fn read(slot: u32, data: &mut [u8; PAGE_SIZE as usize]) -> Result<(), ()> {
+    let (bus, drive, block) = location(slot)?;
+    for (index, chunk) in data.chunks_mut(BLOCK_SIZE).enumerate() {
+        chunk.copy_from_slice(&ata::read_block_uncached(bus, drive, block + index as u32)?);
+    }
+    Ok(())
+}
+
+/// The Drive & First Block Of `slot`. The Lock Isn't Held During I/O.
+fn location(slot: u32) -> Result<(u8, u8, BlockAddr), ()> {
+    without_interrupts(|| {
+        let swap = SWAP.lock();
+        let area = swap.as_ref().ok_or(())?;
+        Ok((area.bus, area.drive, area.block(slot)))
+    })
+}
+
+pub fn csh_swapon(args: ShellArgs) -> ExitCode {
+    if args.len() < 4 {
+        println!("Usage: {} [hdb|hdc|hdd] [First Block] [End Block]", args[0]);
+        return ExitCode::Error(ErrorCode::Usage);
+    }
+
+    let device = match Device::from_str(&args[1]) {
+        Some(device) => device,
+        None => {
+            println!("Invalid Device: '{}'", args[1]);
+            return ExitCode::Error(ErrorCode::Usage);
+        }
+    };
+    let mut range = [0; 2];
+    for (bound, arg) in range.iter_mut().zip(&args[2..4]) {
+        *bound = match arg.parse::<BlockAddr>() {
+            Ok(block) => block,
+            Err(_) => {
+                println!("Invalid Block: '{}'", arg);
+                return ExitCode::Error(ErrorCode::Usage);
+            }
+        };
+    }
+
+    match enable(device, range[0]..range[1].max(range[0])) {
+        Ok(slots) => {
+            println!("Swapping To {} ({} KB)", args[1], slots * 4);
+            ExitCode::Ok
+        }
+        Err(error) => {
+            println!("Failed To Enable Swap: {:?}", error);
+            ExitCode::Error(ErrorCode::FatalError(1))
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Records Every Live Heap Allocation, Enabled By The `debug` Feature.
+//!
+//! The Table Is A Fixed Size Open Addressed Hash Keyed By Pointer, As It Is
+//! Filled From Inside The Global Allocator & So Can't Allocate Itself.
+//! Allocations Made Once The Table Is Full Are Counted But Not Recorded.
+//!
+//! Callers Are Found By Walking The Frame Pointer Chain From The Allocator
+//! (`cargo kgdb` Builds With `-C force-frame-pointers=yes`), As `#[track_caller]` Doesn't Reach
+//! Through Rust's Allocator Shim. Allocations Are Grouped By The Few Return
+//! Addresses Past The Allocator's Own Frames.
+use core::{alloc::Layout, arch::asm, cmp::Reverse, fmt::Display};
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use spin::Mutex;
+use x86_64::{instructions::interrupts::without_interrupts, VirtAddr};
+
+use crate::pit;
+
+const MAX_RECORDS: usize = 8192;
+
+/// Return Addresses Kept Per Allocation.
+pub const DEPTH: usize = 4;
+
+/// Frames Between [`Backtrace::capture`] & The Code Allocating: The
+/// Allocator Entry Point & The Shim Or `mem::malloc` Calling It.
+const SKIPPED: usize = 2;
+
+static TRACKER: Mutex<Tracker> = Mutex::new(Tracker::new());
+
+/// Return Addresses Leading To An Allocation, Innermost First & Zero Past
+/// The End Of The Chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Backtrace(pub [usize; DEPTH]);
+
+impl Backtrace {
+    /// Walks The Frame Pointer Chain Of The Caller. Every Frame Is Checked
+    /// To Be Mapped First, A Fault Here Would Happen Inside The Allocator.
+    #[inline(never)]
+    pub fn capture() -> Self {
+        let mut frame: usize;
+        unsafe { asm!("mov {}, rbp", out(reg) frame, options(nomem, nostack, preserves_flags)) };
+
+        let mut trace = [0; DEPTH];
+        for index in 0..SKIPPED + DEPTH {
+            if !is_frame(frame) {
+                break;
+            }
+            let (next, ret) = unsafe {
+                let frame = frame as *const usize;
+                (*frame, *frame.add(1))
+            };
+            if index >= SKIPPED {
+                trace[index - SKIPPED] = ret;
+            }
+            // Callers' Frames Sit Higher Up The Stack, Threads Start With A
+            // Null One.
+            if next <= frame {
+                break;
+            }
+            frame = next;
+        }
+        Self(trace)
+    }
+}
+
+/// Whether A Saved Frame Pointer & Return Address Can Be Read At `frame`.
+fn is_frame(frame: usize) -> bool {
+    let mapped = |addr: usize| {
+        VirtAddr::try_new(addr as u64).is_ok_and(|addr| super::page_flags(addr).is_some())
+    };
+    frame != 0 && frame & 7 == 0 && mapped(frame) && mapped(frame + 15)
+}
+
+impl Display for Backtrace {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.0[0] == 0 {
+            return write!(f, "Unknown");
+        }
+        for (index, &ret) in self.0.iter().take_while(|&&ret| ret != 0).enumerate() {
+            if index > 0 {
+                write!(f, " <- ")?;
+            }
+            write!(f, "{:#x}", ret)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Record {
+    pub ptr: usize,
+    pub size: usize,
+    pub align: usize,
+    pub trace: Backtrace,
+    /// Milliseconds Since Boot, From The PIT Clock, When The Allocation
+    /// Was Made.
+    pub time: u64,
+}
+
+struct Tracker {
+    records: [Option<Record>; MAX_RECORDS],
+    live: usize,
+    allocations: u64,
+    deallocations: u64,
+    /// Allocations Not Recorded Because The Table Was Full.
+    dropped: u64,
+}
+
+impl Tracker {
+    const fn new() -> Self {
+        Self {
+            records: [None; MAX_RECORDS],
+            live: 0,
+            allocations: 0,
+            deallocations: 0,
+            dropped: 0,
+        }
+    }
+
+    fn slot(ptr: usize) -> usize {
+        // Allocations Are At Least 16 Byte Aligned, So Skip The Zero Bits.
+        (ptr >> 4).wrapping_mul(0x9E37_79B9_7F4A_7C15) % MAX_RECORDS
+    }
+
+    fn insert(&mut self, record: Record) {
+        self.allocations += 1;
+        if self.live == MAX_RECORDS {
+            self.dropped += 1;
+        } else {
+            self.place(record);
+        }
+    }
+
+    fn place(&mut self, record: Record) {
+        let mut slot = Self::slot(record.ptr);
+        while self.records[slot].is_some() {
+            slot = (slot + 1) % MAX_RECORDS;
+        }
+        self.records[slot] = Some(record);
+        self.live += 1;
+    }
+
+    fn find(&self, ptr: usize) -> Option<usize> {
+        let mut slot = Self::slot(ptr);
+        for _ in 0..MAX_RECORDS {
+            match self.records[slot] {
+                Some(record) if record.ptr == ptr => return Some(slot),
+                Some(_) => slot = (slot + 1) % MAX_RECORDS,
+                None => return None,
+            }
+        }
+        None
+    }
+
+    fn remove(&mut self, ptr: usize) {
+        self.deallocations += 1;
+        let slot = match self.find(ptr) {
+            Some(slot) => slot,
+            None => return,
+        };
+        self.records[slot] = None;
+        self.live -= 1;
+
+        // Re-Place The Rest Of The Probe Run So Lookups Don't Stop Early At
+        // The Hole.
+        let mut next = (slot + 1) % MAX_RECORDS;
+        while let Some(record) = self.records[next].take() {
+            self.live -= 1;
+            self.place(record);
+            next = (next + 1) % MAX_RECORDS;
+        }
+    }
+}
+
+/// Records A New Allocation, Called With Interrupts Disabled.
+pub(super) fn record_alloc(ptr: *mut u8, layout: Layout, trace: Backtrace) {
+    if ptr.is_null() {
+        return;
+    }
+    TRACKER.lock().insert(Record {
+        ptr: ptr as usize,
+        size: layout.size(),
+        align: layout.align(),
+        trace,
+        time: pit::uptime(),
+    });
+}
+
+/// Forgets A Freed Allocation, Called With Interrupts Disabled.
+pub(super) fn record_dealloc(ptr: *mut u8) {
+    TRACKER.lock().remove(ptr as usize);
+}
+
+/// Live Allocations Made From One Call Site.
+pub struct CallSite {
+    pub trace: Backtrace,
+    pub count: usize,
+    pub bytes: usize,
+    /// Timestamp Of The Oldest Live Allocation.
+    pub oldest: u64,
+}
+
+/// Returns `(Allocations, Deallocations, Dropped)` Since Boot.
+pub fn counts() -> (u64, u64, u64) {
+    without_interrupts(|| {
+        let tracker = TRACKER.lock();
+        (tracker.allocations, tracker.deallocations, tracker.dropped)
+    })
+}
+
+/// Groups Every Live Allocation By Call Site, Largest Total First.
+pub fn leaks() -> Vec<CallSite> {
+    // Reserve First, Allocating With The Lock Held Would Deadlock.
+    let mut live = Vec::with_capacity(MAX_RECORDS);
+    without_interrupts(|| {
+        let tracker = TRACKER.lock();
+        live.extend(tracker.records.iter().flatten().copied());
+    });
+
+    let mut sites: BTreeMap<Backtrace, CallSite> = BTreeMap::new();
+    for record in live {
+        let site = sites.entry(record.trace).or_insert(CallSite {
+            trace: record.trace,
+            count: 0,
+            bytes: 0,
+            oldest: record.time,
+        });
+        site.count += 1;
+        site.bytes += record.size;
+        site.oldest = site.oldest.min(record.time);
+    }
+
+    let mut sites: Vec<CallSite> = sites.into_values().collect();
+    sites.sort_by_key(|site| Reverse(site.bytes));
+    sites
+}
@@ -13,12 +13,18 @@ use smoltcp::time::Instant;
 use smoltcp::wire::{EthernetAddress, IpCidr, Ipv4Address};
 use spin::Mutex;
 
-use crate::{klog, pci};
+use crate::{
+    clock, klog, pci,
+    task::wake::{self, WakeSource},
+};
 
 mod rtl8139;
 
 pub type Interface = smoltcp::iface::Interface<'static, EthernetDevice>;
 
+/// Longest Wait Between Polls Of The Interface In `poll_async`.
+const POLL_INTERVAL: u64 = 10;
+
 lazy_static! {
     pub static ref IFACE: Mutex<Option<Interface>> = Mutex::new(None);
 }
@@ -213,16 +219,6 @@ impl Stats {
     }
 }
 
-fn find_pci_io_base(vendor_id: u16, device_id: u16) -> Option<u16> {
-    if let Some(mut pci_device) = pci::find_device(vendor_id, device_id) {
-        pci_device.enable_bus_mastering();
-        let io_base = (pci_device.base_addresses[0] as u16) & 0xFFF0;
-        Some(io_base)
-    } else {
-        None
-    }
-}
-
 pub fn init() {
     let add_interface = |device: EthernetDevice, name| {
         if let Some(mac) = device.config().mac() {
@@ -239,7 +235,36 @@ pub fn init() {
             *IFACE.lock() = Some(iface);
         }
     };
-    if let Some(io_base) = find_pci_io_base(0x10EC, 0x8139) {
+    if let Some(mut pci_device) = pci::find_device(0x10EC, 0x8139) {
+        let io_base = pci_device.io_base();
         add_interface(EthernetDevice::RTL8139(rtl8139::Device::new(io_base)), "RTL8139");
+        if let Some(irq) = pci_device.irq() {
+            rtl8139::attach_interrupt(irq, io_base);
+        }
+    }
+}
+
+/// Polls The Interface Once, Returning How Long Until It Next Needs Polling.
+pub fn poll() -> u64 {
+    if let Some(iface) = IFACE.lock().as_mut() {
+        let now = Instant::from_micros((clock::monotonic_ns() / 1000) as i64);
+        if let Err(e) = iface.poll(now) {
+            klog!("NET Poll Error: {}", e);
+        }
+        if let Some(delay) = iface.poll_delay(now) {
+            return delay.total_millis().clamp(1, POLL_INTERVAL);
+        }
     }
-}
\ No newline at end of file
+    POLL_INTERVAL
+}
+
+/// Polls The Interface Forever, Meant To Be Spawned On The Executor. Polls
+/// Again As Soon As The Card Interrupts, Or When smoltcp Next Needs It.
+pub async fn poll_async() {
+    loop {
+        // Counted From Before The Poll, So A Packet Arriving During It
+        // Isn't Missed.
+        let interrupt = wake::interrupt(WakeSource::Net);
+        interrupt.timeout(poll()).await;
+    }
+}
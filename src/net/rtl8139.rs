@@ -2,11 +2,14 @@
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::convert::TryInto;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicU16, AtomicUsize, Ordering};
 use x86_64::instructions::port::Port;
 use smoltcp::wire::EthernetAddress;
 
+use crate::arch::irq;
 use crate::data::physbuffer::PhysBuf;
+use crate::task::wake::{self, WakeSource};
+use crate::{kerr, klog};
 
 use super::{Stats, Config, EthernetDeviceIO};
 
@@ -282,13 +285,38 @@ impl EthernetDeviceIO for Device {
     }
 }
 
-/*
+/// I/O Base Of The Card Raising Interrupts, The Handler Can't Take The
+/// Interface Lock To Reach The Device.
+static IRQ_IO_BASE: AtomicU16 = AtomicU16::new(0);
+
+/// Handles Interrupts From The Card At `io_base` On `irq`.
+pub fn attach_interrupt(irq: u8, io_base: u16) {
+    IRQ_IO_BASE.store(io_base, Ordering::Relaxed);
+    match irq::register(irq, interrupt_handler) {
+        Ok(_) => {
+            klog!("NET RTL8139 On IRQ {}\n", irq);
+        }
+        Err(error) => {
+            kerr!("NET RTL8139 Failed To Register IRQ {}: {:?}\n", irq, error);
+        }
+    }
+}
+
+/// Clears The Card's Interrupt Status & Wakes The Net Poller, The Line May
+/// Be Shared So It Does Nothing If The Card Didn't Raise It.
 pub fn interrupt_handler() {
-    printk!("RTL8139 interrupt!\n");
-    if let Some(mut guard) = sys::net::IFACE.try_lock() {
-        if let Some(ref mut iface) = *guard {
-            unsafe { iface.device_mut().ports.isr.write(0xffff) } // Clear the interrupt
+    let io_base = IRQ_IO_BASE.load(Ordering::Relaxed);
+    if io_base == 0 {
+        return;
+    }
+
+    let mut isr: Port<u16> = Port::new(io_base + 0x3E);
+    unsafe {
+        let status = isr.read();
+        if status == 0 {
+            return;
         }
+        isr.write(status); // Bits Are Cleared By Writing 1
     }
+    wake::wake(WakeSource::Net);
 }
-*/
\ No newline at end of file
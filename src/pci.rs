@@ -87,6 +87,12 @@ impl DeviceConfig {
         register.write(data);
     }
 
+    /// The ISA IRQ The Firmware Wired The Device To, From The Interrupt
+    /// Line Register, `None` If It Has No Interrupt.
+    pub fn irq(&self) -> Option<u8> {
+        (self.interrupt_pin != 0 && self.interrupt_line < 16).then_some(self.interrupt_line)
+    }
+
     pub fn io_base(&mut self) -> u16 {
         self.enable_bus_mastering();
         let io_base = (self.base_addresses[0] as u16) & 0xFFF0;
@@ -1,9 +1,9 @@
 use core::fmt::Display;
 
-use crate::arch;
+use crate::{arch, clock};
 
-/// The PIT Is Clocked At 1.9318166 Mhz.
-pub const PIT_BASE_FREQ: usize = 11931816666;
+/// The PIT Is Clocked At 1.193182 MHz.
+pub const PIT_BASE_FREQ: usize = 1_193_182;
 
 /// Ticks In One Second
 static mut POLLING_FREQ: usize = 18;
@@ -31,10 +31,16 @@ pub fn update_timers() {
     }
 }
 
-pub fn uptime() -> u64 {
+/// Timer Interrupts Since Boot, `polling_rate` A Second.
+pub fn ticks() -> u64 {
     unsafe { GLOBAL_TIMER }
 }
 
+/// Milliseconds Since Boot, From The Monotonic Clock.
+pub fn uptime() -> u64 {
+    clock::monotonic_ms()
+}
+
 pub fn set_frequency(channel: u8, frequency: u16) {
     arch::disable_interrupts();
     let command = ((channel & 0b11) << 6)
@@ -62,14 +68,17 @@ pub fn polling_rate() -> u64 {
 }
 
 pub fn sleep_seconds(seconds: f32) {
-    sleep((polling_rate() as f32 * seconds) as usize);
+    sleep((seconds * 1000.0) as usize);
 }
 
 pub fn sleep(millis: usize) {
-    let start = uptime();
+    if crate::task::is_running() {
+        return crate::task::sleep(millis as u64);
+    }
+
+    let until = clock::monotonic_ns().saturating_add((millis as u64).saturating_mul(1_000_000));
     loop {
-        let now = uptime();
-        if now - start >= (millis as u64) {
+        if clock::monotonic_ns() >= until {
             return;
         }
         crate::sprint!("");
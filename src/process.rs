@@ -0,0 +1,616 @@
+//! User Processes, Their File Descriptors & Exit Statuses.
+use core::{
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use spin::Mutex;
+use x86_64::{instructions::interrupts::without_interrupts, VirtAddr};
+
+use crate::{
+    arch::context::{self, Context},
+    csh::{ErrorCode, ExitCode, ShellArgs},
+    device::{self, CharDevice, CharDeviceIO},
+    elf::{self, ExecError},
+    kerr, klog,
+    locked::Locked,
+    mem::{address_space::AddressSpace, mmap, regions::Fault},
+    println,
+    syscall::Errno,
+    task::{self, scheduler, thread::ThreadId},
+    vfs::{self, drivers::FileIO},
+};
+
+/// The Parent Of Every Process Started By A Kernel Thread.
+pub const KERNEL: Pid = Pid(0);
+
+pub const STDIN: u64 = 0;
+pub const STDOUT: u64 = 1;
+pub const STDERR: u64 = 2;
+
+/// Exit Code Of A Process Killed For An Invalid Memory Access (128 + SIGSEGV).
+pub const SEGFAULT: u8 = 139;
+
+/// Exit Code Of A Process Killed For An Invalid Instruction (128 + SIGILL).
+pub const ILLEGAL_INSTRUCTION: u8 = 132;
+
+/// Exit Code Of A Process Killed For Dividing By Zero (128 + SIGFPE).
+pub const ARITHMETIC_FAULT: u8 = 136;
+
+/// Every Process That Has Not Been Reaped. Only Locked With Interrupts
+/// Disabled & Never Held Across Anything That Can Block, Address Spaces &
+/// Descriptors Have Their Own Locks & Are Cloned Out Of The Table First.
+static PROCESSES: Mutex<BTreeMap<Pid, Process>> = Mutex::new(BTreeMap::new());
+static NEXT_PID: AtomicU64 = AtomicU64::new(1);
+/// Address Spaces Of Reaped Processes, Kept Until Their Thread Is Off
+/// Every CPU. See [`free_retired`].
+static RETIRED: Mutex<Vec<(Option<ThreadId>, SharedSpace)>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Pid(pub u64);
+
+/// An Address Space Shared Between The Process Table & Whoever Is Using It.
+pub type SharedSpace = Arc<Locked<AddressSpace>>;
+
+/// A Descriptor Shared Between The Process Table & A System Call Using It.
+pub type SharedDescriptor = Arc<Locked<Descriptor>>;
+
+impl Display for Pid {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub enum Descriptor {
+    Stdin,
+    Stdout,
+    Stderr,
+    File {
+        file: Box<dyn FileIO>,
+        /// Kept So The File Can Be Reopened By A Forked Child.
+        path: String,
+        offset: usize,
+        /// Read Whole By The First `read`, So Later Reads Are Copies.
+        contents: Option<Vec<u8>>,
+    },
+}
+
+impl Descriptor {
+    fn device(&self) -> Option<&'static mut CharDevice> {
+        match self {
+            Self::Stdin => device::stdin(),
+            Self::Stdout => device::stdout(),
+            Self::Stderr => device::stderr(),
+            Self::File { .. } => None,
+        }
+    }
+
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        match self {
+            Self::Stdin => {
+                let device = self.device().ok_or(Errno::BadFile)?;
+                let mut count = 0;
+                while count < buffer.len() {
+                    match device.read() {
+                        Some(byte) => buffer[count] = byte,
+                        None => break,
+                    }
+                    count += 1;
+                }
+                Ok(count)
+            }
+            Self::File {
+                file,
+                offset,
+                contents,
+                ..
+            } => {
+                let data = contents.get_or_insert_with(|| {
+                    let mut data = file.read_to_vec();
+                    data.truncate(file.size());
+                    data
+                });
+                let start = (*offset).min(data.len());
+                let count = buffer.len().min(data.len() - start);
+                buffer[..count].copy_from_slice(&data[start..start + count]);
+                *offset += count;
+                Ok(count)
+            }
+            _ => Err(Errno::BadFile),
+        }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> Result<usize, Errno> {
+        match self {
+            Self::Stdout | Self::Stderr => {
+                if let Some(device) = self.device() {
+                    for &byte in bytes {
+                        device.write(byte);
+                    }
+                }
+                Ok(bytes.len())
+            }
+            // Files Come From The Read-Only Initrd.
+            _ => Err(Errno::BadFile),
+        }
+    }
+
+    /// Opens The Same Stream Or File Again, At The Same Offset.
+    pub fn duplicate(&self) -> Option<Self> {
+        Some(match self {
+            Self::Stdin => Self::Stdin,
+            Self::Stdout => Self::Stdout,
+            Self::Stderr => Self::Stderr,
+            Self::File { path, offset, .. } => Self::File {
+                file: vfs::open_file(path)?,
+                path: path.clone(),
+                offset: *offset,
+                contents: None,
+            },
+        })
+    }
+
+    pub fn close(&mut self) {
+        if let Self::File { file, .. } = self {
+            file.close();
+        }
+    }
+}
+
+pub struct Process {
+    pid: Pid,
+    parent: Pid,
+    /// The Kernel Thread That Spawned The Process & May Wait For It, As
+    /// Every Kernel Thread Shares The `KERNEL` PID. `None` Once Orphaned.
+    waiter: Option<ThreadId>,
+    thread: Option<ThreadId>,
+    name: String,
+    /// Freed Along With Every Page It Maps Once The Process Is Reaped &
+    /// Nothing Else Holds It.
+    space: SharedSpace,
+    files: BTreeMap<u64, SharedDescriptor>,
+    cwd: String,
+    env: BTreeMap<String, String>,
+    exit_code: Option<u8>,
+}
+
+impl Process {
+    fn new(
+        parent: Pid,
+        name: &str,
+        cwd: String,
+        env: BTreeMap<String, String>,
+        space: AddressSpace,
+    ) -> Self {
+        let pid = Pid(NEXT_PID.fetch_add(1, Ordering::Relaxed));
+
+        let mut files = BTreeMap::new();
+        files.insert(STDIN, Arc::new(Locked::new(Descriptor::Stdin)));
+        files.insert(STDOUT, Arc::new(Locked::new(Descriptor::Stdout)));
+        files.insert(STDERR, Arc::new(Locked::new(Descriptor::Stderr)));
+
+        Self {
+            pid,
+            parent,
+            waiter: None,
+            thread: None,
+            name: name.into(),
+            space: Arc::new(Locked::new(space)),
+            files,
+            cwd,
+            env,
+            exit_code: None,
+        }
+    }
+
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    pub fn parent(&self) -> Pid {
+        self.parent
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn cwd(&self) -> &str {
+        &self.cwd
+    }
+
+    pub fn set_cwd(&mut self, cwd: &str) {
+        self.cwd = cwd.into();
+    }
+
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+
+    pub fn set_env(&mut self, key: &str, value: &str) {
+        self.env.insert(key.into(), value.into());
+    }
+
+    pub fn address_space(&self) -> SharedSpace {
+        self.space.clone()
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.exit_code.is_some()
+    }
+
+    pub fn exit_code(&self) -> Option<ExitCode> {
+        self.exit_code.map(ExitCode::from_unix)
+    }
+
+    pub fn set_exit_code(&mut self, code: u8) {
+        self.exit_code = Some(code);
+    }
+
+    /// Stores `file` Under The Lowest Free Descriptor.
+    pub fn open(&mut self, path: &str, file: Box<dyn FileIO>) -> u64 {
+        let fd = (0..).find(|fd| !self.files.contains_key(fd)).unwrap();
+        let path = path.into();
+        let descriptor = Descriptor::File {
+            file,
+            path,
+            offset: 0,
+            contents: None,
+        };
+        self.files.insert(fd, Arc::new(Locked::new(descriptor)));
+        fd
+    }
+
+    pub fn file(&self, fd: u64) -> Option<SharedDescriptor> {
+        self.files.get(&fd).cloned()
+    }
+
+    /// Removes `fd`, Which The Caller Closes Once It's Out Of The Table.
+    pub fn take_file(&mut self, fd: u64) -> Result<SharedDescriptor, Errno> {
+        self.files.remove(&fd).ok_or(Errno::BadFile)
+    }
+
+    /// Whether No Process Or Thread Is Left To Wait For This One.
+    fn is_orphan(&self) -> bool {
+        self.parent == KERNEL && self.waiter.is_none()
+    }
+
+    /// Whether `pid`, Running On `thread`, May Wait For This Process.
+    fn is_waited_by(&self, pid: Pid, thread: Option<ThreadId>) -> bool {
+        self.parent == pid && (pid != KERNEL || (self.waiter.is_some() && self.waiter == thread))
+    }
+}
+
+/// Runs `f` On The Process Table With Interrupts Disabled. `f` Mustn't
+/// Block, Yield Or Touch Memory That Can Fault.
+fn with<R>(f: impl FnOnce(&mut BTreeMap<Pid, Process>) -> R) -> R {
+    without_interrupts(|| f(&mut PROCESSES.lock()))
+}
+
+/// Runs `f` On The Process Owning The Current Thread, If There Is One.
+pub fn with_current<R>(f: impl FnOnce(&mut Process) -> R) -> Option<R> {
+    let thread = task::current()?;
+    with(|processes| {
+        processes
+            .values_mut()
+            .find(|process| process.thread == Some(thread))
+            .map(f)
+    })
+}
+
+/// The PID Of The Calling Process, Or `KERNEL` From A Kernel Thread.
+pub fn current() -> Pid {
+    with_current(|process| process.pid()).unwrap_or(KERNEL)
+}
+
+/// Resolves A Fault On `addr` In The Calling Process's Address Space, Which
+/// Can Read From Disk, So It Is Done Outside The Process Table. `None` From
+/// A Kernel Thread.
+pub fn resolve_fault(addr: VirtAddr, present: bool, write: bool) -> Option<Result<(), Fault>> {
+    let space = with_current(|process| process.address_space())?;
+    let result = space.lock_yielding().resolve_fault(addr, present, write);
+    Some(result)
+}
+
+/// Loads The Executable At `path` & Starts It As A Child Of The Caller.
+pub fn spawn(path: &str, args: &[String]) -> Result<Pid, ExecError> {
+    let file = vfs::open_file(path).ok_or(ExecError::NotFound)?;
+
+    let parent = current();
+    let (cwd, env) = with_current(|process| (process.cwd.clone(), process.env.clone()))
+        .unwrap_or_else(|| (String::from("/"), BTreeMap::new()));
+
+    let envp: Vec<String> = env
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    let mut space = AddressSpace::new();
+    let program = elf::load(&file.read_to_vec(), args, &envp, &mut space)?;
+
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let mut process = Process::new(parent, name, cwd, env, space);
+    if parent == KERNEL {
+        process.waiter = task::current();
+    }
+    Ok(start(process, move || unsafe {
+        context::enter_user(program.entry, program.stack_pointer)
+    }))
+}
+
+/// Duplicates The Calling Process, Whose Registers Are `context`. The Child
+/// Shares Its Parent's Pages Copy-On-Write & Resumes From The Same System
+/// Call, Seeing A Return Value Of 0.
+pub fn fork(context: &Context) -> Result<Pid, Errno> {
+    // Copying The Address Space Can Read From Swap & Duplicating A File Opens
+    // It Again, So Only Handles Are Taken From The Table.
+    let (parent, name, cwd, env, space, files) = with_current(|process| {
+        (
+            process.pid,
+            process.name.clone(),
+            process.cwd.clone(),
+            process.env.clone(),
+            process.address_space(),
+            process.files.clone(),
+        )
+    })
+    .ok_or(Errno::Invalid)?;
+
+    let space = space.lock_yielding().fork();
+    let mut process = Process::new(parent, &name, cwd, env, space);
+    process.files = files
+        .iter()
+        .filter_map(|(&fd, file)| {
+            let file = file.lock_yielding().duplicate()?;
+            Some((fd, Arc::new(Locked::new(file))))
+        })
+        .collect();
+
+    let mut regs = *context;
+    regs.rax = 0;
+
+    let pid = start(process, move || unsafe { context::resume_user(&regs) });
+    klog!("Forked Process {} From {}\n", pid, parent);
+    Ok(pid)
+}
+
+/// Runs `main` On A New Thread Belonging To `process`.
+fn start<F: FnOnce() + Send + 'static>(mut process: Process, main: F) -> Pid {
+    let pid = process.pid();
+
+    free_retired();
+
+    // The Process Must Be In The Table & Its Thread Must Know Its Address
+    // Space Before It First Runs.
+    with(|processes| {
+        // Only Used To Load CR3 On The Process's Own Thread.
+        let space = {
+            let mut space = process.space.lock();
+            &mut *space as *mut AddressSpace
+        };
+        let thread = task::spawn_in(&process.name, space, main);
+        process.thread = Some(thread.id());
+        processes.insert(pid, process);
+    });
+    pid
+}
+
+/// Records The Exit Code Of The Calling Process & Terminates It, Writing
+/// Back Its File Mappings First. Its Live Children Are Orphaned, & Reaped
+/// As They Exit, While Exited Ones & The Process Itself If It Is An Orphan
+/// Are Reaped Straight Away.
+pub fn exit(code: u8) -> ! {
+    if let Some(space) = with_current(|process| process.address_space()) {
+        if let Err(error) = mmap::msync_all(&mut space.lock_yielding()) {
+            kerr!("Failed To Sync Process {} On Exit: {:?}\n", current(), error);
+        }
+    }
+
+    if let Some(thread) = task::current() {
+        let unwaited = with(|processes| {
+            let process = processes
+                .values_mut()
+                .find(|process| process.thread == Some(thread))?;
+            process.set_exit_code(code);
+            let pid = process.pid;
+
+            for child in processes.values_mut().filter(|child| child.parent == pid) {
+                child.parent = KERNEL;
+                child.waiter = None;
+            }
+            Some(
+                processes
+                    .values()
+                    .filter(|process| process.is_orphan() && process.has_exited())
+                    .map(|process| process.pid)
+                    .collect::<Vec<_>>(),
+            )
+        });
+
+        for pid in unwaited.into_iter().flatten() {
+            reap(pid);
+        }
+    }
+    task::exit(code)
+}
+
+/// Removes `pid` From The Table If It Has Exited, Freeing Its Address Space.
+fn reap(pid: Pid) -> Option<ExitCode> {
+    let process = with(|processes| {
+        if processes.get(&pid)?.has_exited() {
+            processes.remove(&pid)
+        } else {
+            None
+        }
+    })?;
+
+    // The Thread May Not Be Reaped Yet, Make Sure It Never Loads The Freed
+    // Address Space.
+    if let Some(thread) = process.thread {
+        scheduler::with(|scheduler| {
+            if let Some(thread) = scheduler.get_mut(thread) {
+                thread.set_address_space(core::ptr::null_mut());
+            }
+        });
+    }
+    let code = process.exit_code();
+    without_interrupts(|| {
+        RETIRED
+            .lock()
+            .push((process.thread, process.space.clone()))
+    });
+    drop(process);
+    free_retired();
+    code
+}
+
+/// Frees The Address Spaces Of Reaped Processes Whose Thread Has Left Every
+/// CPU, Until Then A CPU May Still Have Their Tables In CR3.
+fn free_retired() {
+    let freed: Vec<SharedSpace> = without_interrupts(|| {
+        let mut retired = RETIRED.lock();
+        let mut freed = Vec::new();
+        let mut index = 0;
+        while index < retired.len() {
+            match retired[index].0 {
+                Some(thread) if task::is_on_cpu(thread) => index += 1,
+                _ => freed.push(retired.swap_remove(index).1),
+            }
+        }
+        freed
+    });
+    drop(freed);
+}
+
+/// Blocks Until The Child `pid` Exits, Returning Its Exit Code.
+pub fn waitpid(pid: Pid) -> Result<ExitCode, Errno> {
+    let (parent, thread) = (current(), task::current());
+    loop {
+        let is_child = with(|processes| {
+            processes
+                .get(&pid)
+                .map(|process| process.is_waited_by(parent, thread))
+        });
+        match is_child {
+            Some(true) => {}
+            _ => return Err(Errno::NoChild),
+        }
+
+        if let Some(code) = reap(pid) {
+            return Ok(code);
+        }
+        task::yield_now();
+    }
+}
+
+/// Blocks Until Any Child Of The Caller Exits, Returning Its PID & Exit Code.
+pub fn wait() -> Result<(Pid, ExitCode), Errno> {
+    let (parent, thread) = (current(), task::current());
+    loop {
+        let (children, exited) = with(|processes| {
+            let mut children = processes
+                .values()
+                .filter(|process| process.is_waited_by(parent, thread));
+            let count = children.clone().count();
+            (
+                count,
+                children
+                    .find(|process| process.has_exited())
+                    .map(|process| process.pid),
+            )
+        });
+
+        if children == 0 {
+            return Err(Errno::NoChild);
+        }
+        if let Some(pid) = exited {
+            if let Some(code) = reap(pid) {
+                return Ok((pid, code));
+            }
+        }
+        task::yield_now();
+    }
+}
+
+/// Writes Back The File Mappings Of Every Process, Returning How Many
+/// Processes Failed To. The Spaces Are Collected First & Written Back
+/// Outside The Process Table.
+pub fn sync_all() -> usize {
+    let spaces: Vec<(Pid, SharedSpace)> = with(|processes| {
+        processes
+            .values()
+            .map(|process| (process.pid, process.address_space()))
+            .collect()
+    });
+
+    let mut failed = 0;
+    for (pid, space) in spaces {
+        if let Err(error) = mmap::msync_all(&mut space.lock_yielding()) {
+            kerr!("Failed To Sync Process {}: {:?}\n", pid, error);
+            failed += 1;
+        }
+    }
+    failed
+}
+
+/// Every Process's Address Space, Collected Outside The Process Table.
+pub fn address_spaces() -> Vec<SharedSpace> {
+    with(|processes| processes.values().map(Process::address_space).collect())
+}
+
+/// Returns `(Pid, Parent, Name, Exited)` For Every Process.
+pub fn list() -> Vec<(Pid, Pid, String, bool)> {
+    with(|processes| {
+        processes
+            .values()
+            .map(|process| {
+                (
+                    process.pid,
+                    process.parent,
+                    process.name.to_string(),
+                    process.has_exited(),
+                )
+            })
+            .collect()
+    })
+}
+
+/// Runs The Executable At `path` & Waits For It To Exit.
+pub fn exec(path: &str, args: &[String]) -> Result<ExitCode, ExecError> {
+    let pid = spawn(path, args)?;
+    Ok(waitpid(pid).unwrap_or(ExitCode::Error(ErrorCode::General)))
+}
+
+pub fn exec_main(args: ShellArgs) -> ExitCode {
+    if args.len() < 2 {
+        println!("Usage: {} <filepath> [args...]", args[0]);
+        return ExitCode::Error(ErrorCode::Usage);
+    }
+
+    match exec(&args[1], &args[1..]) {
+        Ok(code) => code,
+        Err(ExecError::NotFound) => {
+            println!("Error: File Not Found: '{}'", args[1]);
+            ExitCode::Error(ErrorCode::General)
+        }
+        Err(err) => {
+            klog!("Failed To Execute '{}': {:?}\n", args[1], err);
+            ExitCode::Error(ErrorCode::CommandCannotExecute)
+        }
+    }
+}
+
+/// Waits For & Reaps Every Child Of The Shell.
+pub fn wait_main(_: ShellArgs) -> ExitCode {
+    while let Ok((pid, code)) = wait() {
+        println!("[{}] Exited ({})", pid, code.unix());
+    }
+    ExitCode::Ok
+}
@@ -0,0 +1,300 @@
+//! System Calls Made Through `int 0x80`.
+//!
+//! The Number Is Passed In RAX & Up To Three Arguments In RDI, RSI & RDX,
+//! Matching `asm/libs/syscalls.asm`. The Result Is Returned In RAX, Errors
+//! Are Returned As A Negated `Errno`.
+use crate::{
+    arch::context::Context,
+    mem::{
+        self,
+        mmap::{self, MmapError},
+        regions,
+        shared::{self, ShmError},
+        PTFlags, VirtAddr,
+    },
+    process::{self, Descriptor, SharedSpace, STDERR, STDIN, STDOUT},
+    task,
+    vfs::{
+        self,
+        drivers::{simple_fat, VirtFileSystem},
+    },
+};
+
+pub const OPEN: u64 = 0;
+pub const WRITE: u64 = 1;
+pub const READ: u64 = 2;
+pub const CLOSE: u64 = 3;
+pub const SLEEP: u64 = 4;
+pub const EXIT: u64 = 5;
+pub const FORK: u64 = 6;
+pub const SHM_CREATE: u64 = 7;
+pub const SHM_MAP: u64 = 8;
+pub const SHM_UNLINK: u64 = 9;
+pub const MMAP: u64 = 10;
+pub const MUNMAP: u64 = 11;
+
+/// The First Address Past The Lower (User) Half.
+const USER_END: u64 = 0x0000_8000_0000_0000;
+/// Longest Path Accepted By `open`, Including The Terminator.
+const PATH_MAX: usize = 256;
+
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Errno {
+    NoEntry = 2,
+    Io = 5,
+    BadFile = 9,
+    NoChild = 10,
+    Fault = 14,
+    Exists = 17,
+    Invalid = 22,
+    ReadOnly = 30,
+    NoSys = 38,
+}
+
+#[derive(Clone, Copy)]
+enum Handler {
+    /// Takes The Three Argument Registers.
+    Args(fn(u64, u64, u64) -> Result<u64, Errno>),
+    /// Takes Every Register Of The Caller, Like `fork`.
+    Context(fn(&mut Context) -> Result<u64, Errno>),
+}
+
+/// Indexed By The System Call Number.
+static SYSCALLS: [Handler; 12] = [
+    Handler::Args(open),
+    Handler::Args(write),
+    Handler::Args(read),
+    Handler::Args(close),
+    Handler::Args(sleep),
+    Handler::Args(exit),
+    Handler::Context(fork),
+    Handler::Args(shm_create),
+    Handler::Args(shm_map),
+    Handler::Args(shm_unlink),
+    Handler::Args(mmap),
+    Handler::Args(munmap),
+];
+
+/// Handles The System Call Described By `regs`, Storing The Result In RAX.
+pub fn dispatch(regs: &mut Context) {
+    let result = match SYSCALLS.get(regs.rax as usize) {
+        Some(Handler::Args(handler)) => handler(regs.rdi, regs.rsi, regs.rdx),
+        Some(Handler::Context(handler)) => handler(regs),
+        None => Err(Errno::NoSys),
+    };
+
+    regs.rax = match result {
+        Ok(value) => value,
+        Err(errno) => (errno as u64).wrapping_neg(),
+    };
+}
+
+/// Checks That `ptr..ptr + len` Lies In The User Half & Is Mapped For Ring 3.
+fn check_user(ptr: u64, len: u64, writable: bool) -> Result<(), Errno> {
+    let end = ptr.checked_add(len).ok_or(Errno::Fault)?;
+    if ptr == 0 || end > USER_END {
+        return Err(Errno::Fault);
+    }
+
+    let mut page = ptr & !0xFFF;
+    while page < end {
+        let addr = VirtAddr::new(page);
+        let mut flags = mem::page_flags(addr);
+        let usable = matches!(flags, Some(flags) if !writable || flags.contains(PTFlags::WRITABLE));
+        if !usable {
+            // The Kernel Ignores Read-Only Pages & Doesn't Fault Stack Pages
+            // In, So Resolve The Fault The Access Would Have Caused.
+            let present = flags.is_some();
+            let _ = process::resolve_fault(addr, present, writable);
+            flags = mem::page_flags(addr);
+        }
+
+        let flags = flags.ok_or(Errno::Fault)?;
+        if !flags.contains(PTFlags::USER_ACCESSIBLE)
+            || (writable && !flags.contains(PTFlags::WRITABLE))
+        {
+            return Err(Errno::Fault);
+        }
+        page += 4096;
+    }
+    Ok(())
+}
+
+fn user_slice<'a>(ptr: u64, len: u64) -> Result<&'a [u8], Errno> {
+    check_user(ptr, len, false)?;
+    Ok(unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) })
+}
+
+fn user_slice_mut<'a>(ptr: u64, len: u64) -> Result<&'a mut [u8], Errno> {
+    check_user(ptr, len, true)?;
+    Ok(unsafe { core::slice::from_raw_parts_mut(ptr as *mut u8, len as usize) })
+}
+
+/// Reads A NUL-Terminated String Of At Most `PATH_MAX` Bytes.
+fn user_str<'a>(ptr: u64) -> Result<&'a str, Errno> {
+    for len in 0..PATH_MAX as u64 {
+        check_user(ptr + len, 1, false)?;
+        if unsafe { *((ptr + len) as *const u8) } == 0 {
+            let bytes = user_slice(ptr, len)?;
+            return core::str::from_utf8(bytes).map_err(|_| Errno::Invalid);
+        }
+    }
+    Err(Errno::Invalid)
+}
+
+/// Runs `f` On The Caller's Descriptor `fd`, Outside The Process Table As
+/// File I/O Can Block.
+fn with_file<R>(fd: u64, f: impl FnOnce(&mut Descriptor) -> Result<R, Errno>) -> Result<R, Errno> {
+    if process::current() == process::KERNEL {
+        // Kernel Threads Only Have The Standard Streams.
+        let mut stream = match fd {
+            STDIN => Descriptor::Stdin,
+            STDOUT => Descriptor::Stdout,
+            STDERR => Descriptor::Stderr,
+            _ => return Err(Errno::BadFile),
+        };
+        return f(&mut stream);
+    }
+
+    let file = process::with_current(|process| process.file(fd))
+        .flatten()
+        .ok_or(Errno::BadFile)?;
+    let result = f(&mut file.lock_yielding());
+    result
+}
+
+fn open(path: u64, _options: u64, _: u64) -> Result<u64, Errno> {
+    let path = user_str(path)?;
+    let file = vfs::open_file(path).ok_or(Errno::NoEntry)?;
+    process::with_current(|process| process.open(path, file)).ok_or(Errno::BadFile)
+}
+
+fn write(fd: u64, buffer: u64, len: u64) -> Result<u64, Errno> {
+    let bytes = user_slice(buffer, len)?;
+    with_file(fd, |file| file.write(bytes)).map(|count| count as u64)
+}
+
+fn read(fd: u64, buffer: u64, len: u64) -> Result<u64, Errno> {
+    let buffer = user_slice_mut(buffer, len)?;
+    with_file(fd, |file| file.read(buffer)).map(|count| count as u64)
+}
+
+fn close(fd: u64, _: u64, _: u64) -> Result<u64, Errno> {
+    let file = process::with_current(|process| process.take_file(fd)).ok_or(Errno::BadFile)??;
+    file.lock_yielding().close();
+    Ok(0)
+}
+
+/// `sleep(double seconds)`, The Seconds Are Passed As Raw IEEE-754 Bits.
+/// Lengths Too Long To Count In Milliseconds Sleep For As Long As Possible.
+fn sleep(seconds: u64, _: u64, _: u64) -> Result<u64, Errno> {
+    let seconds = f64::from_bits(seconds);
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err(Errno::Invalid);
+    }
+    let millis = seconds * 1000.0;
+    task::sleep(if millis >= u64::MAX as f64 { u64::MAX } else { millis as u64 });
+    Ok(0)
+}
+
+fn exit(code: u64, _: u64, _: u64) -> Result<u64, Errno> {
+    process::exit(code as u8)
+}
+
+fn fork(regs: &mut Context) -> Result<u64, Errno> {
+    process::fork(regs).map(|pid| pid.0)
+}
+
+/// The Calling Process's Address Space, Kernel Threads Don't Have One.
+fn current_space() -> Result<SharedSpace, Errno> {
+    process::with_current(|process| process.address_space()).ok_or(Errno::Invalid)
+}
+
+/// Checks That A Mapping Of `size` Bytes At `addr` Is Page Aligned, Lies
+/// In The User Half & Stays Clear Of Kernel Memory.
+fn check_mapping(addr: u64, size: u64) -> Result<(), Errno> {
+    let fits = matches!(
+        addr.checked_add(size),
+        Some(end) if end <= USER_END && !regions::overlaps_kernel(addr, end)
+    );
+    if addr == 0 || addr & 0xFFF != 0 || !fits {
+        return Err(Errno::Invalid);
+    }
+    Ok(())
+}
+
+fn mapping_flags(writable: u64) -> PTFlags {
+    let mut flags = PTFlags::PRESENT | PTFlags::USER_ACCESSIBLE | PTFlags::NO_EXECUTE;
+    if writable != 0 {
+        flags |= PTFlags::WRITABLE;
+    }
+    flags
+}
+
+/// `shm_create(name, size)`, Creates A Named Shared Memory Region Of At
+/// Least `size` Bytes, Returning Its Size.
+fn shm_create(name: u64, size: u64, _: u64) -> Result<u64, Errno> {
+    let name = user_str(name)?;
+    if size == 0 || size > USER_END {
+        return Err(Errno::Invalid);
+    }
+    match shared::create(name, size as usize) {
+        Ok(region) => Ok(region.size() as u64),
+        Err(_) => Err(Errno::Exists),
+    }
+}
+
+/// `shm_map(name, addr, writable)`, Maps The Whole Region At The Page
+/// Aligned `addr`, Returning Its Size.
+fn shm_map(name: u64, addr: u64, writable: u64) -> Result<u64, Errno> {
+    let region = shared::open(user_str(name)?).ok_or(Errno::NoEntry)?;
+    let size = region.size() as u64;
+    check_mapping(addr, size)?;
+
+    let space = current_space()?;
+    let flags = mapping_flags(writable);
+    let result = region.map(&mut space.lock_yielding(), VirtAddr::new(addr), flags);
+    match result {
+        Ok(()) => Ok(size),
+        Err(ShmError::AddressInUse(_)) => Err(Errno::Exists),
+        Err(_) => Err(Errno::Invalid),
+    }
+}
+
+/// `shm_unlink(name)`, Forgets A Region's Name. Existing Mappings Stay.
+fn shm_unlink(name: u64, _: u64, _: u64) -> Result<u64, Errno> {
+    shared::remove(user_str(name)?).map(|_| 0).ok_or(Errno::NoEntry)
+}
+
+/// `mmap(path, addr, writable)`, Maps A File Of The Mounted Disk At The
+/// Page Aligned `addr`, Returning The Mapping's Length. Pages Are Read As
+/// They Are Touched & Written Back By `munmap` Or When The Process Exits.
+fn mmap(path: u64, addr: u64, writable: u64) -> Result<u64, Errno> {
+    let path = user_str(path)?;
+    let fs = simple_fat::FileSystem;
+    let extent = fs.locate(path).ok_or(Errno::NoEntry)?;
+    check_mapping(addr, (extent.size as u64).max(1))?;
+
+    let space = current_space()?;
+    let flags = mapping_flags(writable);
+    let result = mmap::map(&mut space.lock_yielding(), &fs, path, VirtAddr::new(addr), flags);
+    result.map(|len| len as u64).map_err(mmap_errno)
+}
+
+/// `munmap(addr)`, Writes Back & Removes The File Mapped Over `addr`.
+fn munmap(addr: u64, _: u64, _: u64) -> Result<u64, Errno> {
+    let space = current_space()?;
+    let result = mmap::unmap(&mut space.lock_yielding(), VirtAddr::new_truncate(addr));
+    result.map(|_| 0).map_err(mmap_errno)
+}
+
+fn mmap_errno(error: MmapError) -> Errno {
+    match error {
+        MmapError::NotFound => Errno::NoEntry,
+        MmapError::AddressInUse(_) => Errno::Exists,
+        MmapError::ReadOnly => Errno::ReadOnly,
+        MmapError::NotMapped(_) => Errno::Invalid,
+        MmapError::Io(_) => Errno::Io,
+    }
+}
@@ -0,0 +1,160 @@
+//! Kernel Threads, The Round-Robin Scheduler & The Async Executor.
+use alloc::boxed::Box;
+
+use crate::{
+    arch::{self, context},
+    csh::{ExitCode, ShellArgs},
+    mem::address_space::AddressSpace,
+    pit, println,
+};
+
+pub mod executor;
+pub mod scheduler;
+pub mod thread;
+pub mod wake;
+
+use self::thread::{State, Thread, ThreadId};
+
+type ThreadMain = Box<dyn FnOnce() + Send + 'static>;
+
+/// A Handle To A Spawned Thread, Dropping It Detaches The Thread.
+#[must_use]
+pub struct JoinHandle {
+    id: ThreadId,
+}
+
+impl JoinHandle {
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    /// Blocks Until The Thread Exits, Returning Its Exit Code.
+    pub fn join(self) -> u8 {
+        let id = self.id;
+        core::mem::forget(self);
+        loop {
+            let code = scheduler::with(|scheduler| {
+                let code = scheduler.reap(id);
+                if code.is_none() {
+                    scheduler.current_mut().set_state(State::Joining(id));
+                }
+                code
+            })
+            .flatten();
+
+            match code {
+                Some(code) => return code,
+                None => yield_now(),
+            }
+        }
+    }
+}
+
+impl Drop for JoinHandle {
+    fn drop(&mut self) {
+        scheduler::with(|scheduler| {
+            if let Some(thread) = scheduler.get_mut(self.id) {
+                thread.detach();
+            }
+        });
+    }
+}
+
+/// Starts The Scheduler, The Caller Becomes Thread #0.
+pub fn init() {
+    scheduler::init(Thread::new(ThreadId(u64::MAX), "idle", idle_main, 0));
+}
+
+pub fn is_running() -> bool {
+    scheduler::is_running()
+}
+
+/// Spawns A Kernel Thread Running `main`.
+///
+/// ## Panics
+/// - Panics If The Scheduler Has Not Been Initialized.
+pub fn spawn<F: FnOnce() + Send + 'static>(name: &str, main: F) -> JoinHandle {
+    spawn_in(name, core::ptr::null_mut(), main)
+}
+
+/// Spawns A Thread Running `main` With `space` Loaded, Attached Before The
+/// Thread Can First Be Scheduled.
+///
+/// ## Panics
+/// - Panics If The Scheduler Has Not Been Initialized.
+pub fn spawn_in<F: FnOnce() + Send + 'static>(
+    name: &str,
+    space: *mut AddressSpace,
+    main: F,
+) -> JoinHandle {
+    let main: Box<ThreadMain> = Box::new(Box::new(main));
+    let arg = Box::into_raw(main) as u64;
+
+    scheduler::with(|scheduler| {
+        scheduler.reap_detached();
+        let id = scheduler.next_id();
+        let mut thread = Thread::new(id, name, thread_main, arg);
+        thread.set_address_space(space);
+        scheduler.add(thread);
+        JoinHandle { id }
+    })
+    .expect("Scheduler Not Initialized")
+}
+
+/// Gives Up The Rest Of The Current Time Slice.
+pub fn yield_now() {
+    if is_running() {
+        context::yield_cpu();
+    } else {
+        arch::pause();
+    }
+}
+
+/// Puts The Current Thread To Sleep For At Least `millis` Milliseconds.
+pub fn sleep(millis: u64) {
+    if !is_running() {
+        return pit::sleep(millis as usize);
+    }
+
+    let until = pit::uptime().saturating_add(millis);
+    scheduler::with(|scheduler| scheduler.current_mut().set_state(State::Sleeping(until)));
+    while pit::uptime() < until {
+        yield_now();
+    }
+}
+
+/// Terminates The Current Thread.
+pub fn exit(code: u8) -> ! {
+    scheduler::with(|scheduler| scheduler.current_mut().set_state(State::Exited(code)));
+    loop {
+        yield_now();
+    }
+}
+
+/// Whether `id` Is Running On, Or Still Being Switched Away From, Any CPU.
+pub fn is_on_cpu(id: ThreadId) -> bool {
+    scheduler::with(|scheduler| scheduler.is_on_cpu(id)).unwrap_or(false)
+}
+
+pub fn current() -> Option<ThreadId> {
+    scheduler::with(|scheduler| scheduler.current().id())
+}
+
+extern "C" fn thread_main(main: u64) -> ! {
+    let main = unsafe { Box::from_raw(main as *mut ThreadMain) };
+    (*main)();
+    exit(0)
+}
+
+extern "C" fn idle_main(_: u64) -> ! {
+    loop {
+        arch::pause();
+    }
+}
+
+pub fn ps(_: ShellArgs) -> ExitCode {
+    for (id, name, state) in scheduler::list() {
+        println!("{:>4} {:<12} {}", id.0, name, state);
+    }
+    ExitCode::Ok
+}
@@ -0,0 +1,281 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    task::Wake,
+    vec::Vec,
+};
+use conquer_once::spin::OnceCell;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::locked::Locked;
+
+/// Most Woken Tasks Queued At Once, Past That Every Task Is Polled.
+const READY_CAPACITY: usize = 128;
+
+static QUEUES: OnceCell<Arc<Queues>> = OnceCell::uninit();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TaskId(u64);
+
+impl TaskId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+pub struct Task {
+    id: TaskId,
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
+}
+
+impl Task {
+    pub fn new(future: impl Future<Output = ()> + Send + 'static) -> Self {
+        Self {
+            id: TaskId::new(),
+            future: Box::pin(future),
+        }
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    fn poll(&mut self, context: &mut Context) -> Poll<()> {
+        self.future.as_mut().poll(context)
+    }
+}
+
+/// Woken Tasks Waiting To Be Polled.
+///
+/// Fixed Size So Waking Never Allocates Inside An Interrupt Handler. A Task
+/// Is Only Queued Once, & If The Queue Still Fills Up It Overflows Into
+/// Polling Every Task, As A Spurious Poll Is Harmless.
+struct ReadyQueue {
+    ids: [TaskId; READY_CAPACITY],
+    head: usize,
+    len: usize,
+    overflowed: bool,
+}
+
+impl ReadyQueue {
+    fn new() -> Self {
+        Self {
+            ids: [TaskId(0); READY_CAPACITY],
+            head: 0,
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    fn contains(&self, id: TaskId) -> bool {
+        (0..self.len).any(|i| self.ids[(self.head + i) % READY_CAPACITY] == id)
+    }
+
+    fn push(&mut self, id: TaskId) {
+        if self.contains(id) {
+            return;
+        }
+        if self.len == READY_CAPACITY {
+            self.overflowed = true;
+            return;
+        }
+        self.ids[(self.head + self.len) % READY_CAPACITY] = id;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<TaskId> {
+        if self.len == 0 {
+            return None;
+        }
+        let id = self.ids[self.head];
+        self.head = (self.head + 1) % READY_CAPACITY;
+        self.len -= 1;
+        Some(id)
+    }
+
+    /// Whether Wakes Were Dropped Since The Last Call.
+    fn take_overflow(&mut self) -> bool {
+        core::mem::take(&mut self.overflowed)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0 && !self.overflowed
+    }
+}
+
+/// Queues Shared Between The Executor, Its Spawners And Its Wakers.
+///
+/// Wakers Are Fired From Interrupt Handlers, So Every Access Happens With
+/// Interrupts Disabled.
+struct Queues {
+    ready: Locked<ReadyQueue>,
+    spawned: Locked<VecDeque<Task>>,
+}
+
+impl Queues {
+    fn new() -> Self {
+        Self {
+            ready: Locked::new(ReadyQueue::new()),
+            spawned: Locked::new(VecDeque::new()),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ready.lock().is_empty() && self.spawned.lock().is_empty()
+    }
+}
+
+/// A Cloneable Handle For Adding Tasks To An `Executor`.
+#[derive(Clone)]
+pub struct Spawner {
+    queues: Arc<Queues>,
+}
+
+impl Spawner {
+    pub fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) -> TaskId {
+        let task = Task::new(future);
+        let id = task.id();
+        without_interrupts(|| self.queues.spawned.lock().push_back(task));
+        id
+    }
+}
+
+struct TaskWaker {
+    id: TaskId,
+    queues: Arc<Queues>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        without_interrupts(|| self.queues.ready.lock().push(self.id));
+    }
+}
+
+pub struct Executor {
+    tasks: BTreeMap<TaskId, Task>,
+    wakers: BTreeMap<TaskId, Waker>,
+    queues: Arc<Queues>,
+}
+
+impl Executor {
+    fn with_queues(queues: Arc<Queues>) -> Self {
+        Self {
+            tasks: BTreeMap::new(),
+            wakers: BTreeMap::new(),
+            queues,
+        }
+    }
+
+    pub fn spawner(&self) -> Spawner {
+        Spawner {
+            queues: self.queues.clone(),
+        }
+    }
+
+    pub fn spawn(&mut self, future: impl Future<Output = ()> + Send + 'static) -> TaskId {
+        self.spawner().spawn(future)
+    }
+
+    pub fn task_count(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Polls Tasks Until None Are Ready.
+    pub fn run_until_idle(&mut self) {
+        loop {
+            let spawned = without_interrupts(|| self.queues.spawned.lock().pop_front());
+            if let Some(task) = spawned {
+                let id = task.id();
+                self.tasks.insert(id, task);
+                self.poll_task(id);
+                continue;
+            }
+
+            if without_interrupts(|| self.queues.ready.lock().take_overflow()) {
+                let ids: Vec<TaskId> = self.tasks.keys().copied().collect();
+                for id in ids {
+                    self.poll_task(id);
+                }
+                continue;
+            }
+
+            let ready = without_interrupts(|| self.queues.ready.lock().pop());
+            match ready {
+                Some(id) => self.poll_task(id),
+                None => return,
+            }
+        }
+    }
+
+    /// Runs Forever, Yielding To Other Threads While No Task Is Ready.
+    pub fn run(&mut self) -> ! {
+        loop {
+            self.run_until_idle();
+            self.sleep_if_idle();
+        }
+    }
+
+    fn poll_task(&mut self, id: TaskId) {
+        let queues = &self.queues;
+        let task = match self.tasks.get_mut(&id) {
+            Some(task) => task,
+            // Woken after it already completed.
+            None => return,
+        };
+
+        let waker = self.wakers.entry(id).or_insert_with(|| {
+            Waker::from(Arc::new(TaskWaker {
+                id,
+                queues: queues.clone(),
+            }))
+        });
+
+        let mut context = Context::from_waker(waker);
+        if let Poll::Ready(()) = task.poll(&mut context) {
+            self.tasks.remove(&id);
+            self.wakers.remove(&id);
+        }
+    }
+
+    /// Halting Here Would Stall Every Thread On The Core Until The Next
+    /// Interrupt, So The Rest Of The Quantum Is Given Away Instead.
+    fn sleep_if_idle(&self) {
+        if without_interrupts(|| self.queues.is_empty()) {
+            crate::task::yield_now();
+        }
+    }
+}
+
+fn global_queues() -> Arc<Queues> {
+    QUEUES.get_or_init(|| Arc::new(Queues::new())).clone()
+}
+
+/// Runs The Global Executor On The Calling Thread.
+pub fn run() -> ! {
+    Executor::with_queues(global_queues()).run()
+}
+
+/// Returns A Spawner For The Global Executor.
+pub fn spawner() -> Spawner {
+    Spawner {
+        queues: global_queues(),
+    }
+}
+
+/// Spawns `future` On The Global Executor, It Is Queued Until `run` Is Called.
+pub fn spawn(future: impl Future<Output = ()> + Send + 'static) -> TaskId {
+    spawner().spawn(future)
+}
@@ -0,0 +1,289 @@
+use alloc::{string::String, vec, vec::Vec};
+use conquer_once::spin::OnceCell;
+use x86_64::{instructions::interrupts::without_interrupts, VirtAddr};
+
+use crate::{
+    arch::{context::Context, gdt, smp},
+    locked::Locked,
+    mem::address_space,
+    pit,
+};
+
+use super::thread::{State, Thread, ThreadId};
+
+/// Number Of Timer Ticks (Milliseconds) A Thread Runs Before It Is Preempted.
+pub const QUANTUM: u64 = 10;
+
+static SCHEDULER: OnceCell<Locked<Scheduler>> = OnceCell::uninit();
+
+/// A Round-Robin Scheduler Over Every Kernel Thread, Shared By All CPUs.
+///
+/// `schedule` Runs Inside The Timer And Yield Interrupts, So It Must Never
+/// Allocate; Threads Are Only Added Or Removed With Interrupts Disabled.
+pub struct Scheduler {
+    threads: Vec<Thread>,
+    cpus: Vec<CpuSlot>,
+    next_id: u64,
+}
+
+/// What One CPU Is Running, By Index Into `threads`.
+struct CpuSlot {
+    cpu: usize,
+    current: usize,
+    /// Runs Only On This CPU, When Nothing Else Can.
+    idle: usize,
+    /// The Thread Last Switched Away From, Whose Stack The CPU Was Still
+    /// On Until It Resumed `current`. Kept Off Other CPUs Until Then.
+    previous: Option<ThreadId>,
+    slice_start: u64,
+}
+
+impl Scheduler {
+    fn new() -> Self {
+        Self {
+            threads: vec![Thread::boot(ThreadId(0), "kernel")],
+            cpus: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn next_id(&mut self) -> ThreadId {
+        let id = ThreadId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    pub fn add(&mut self, thread: Thread) {
+        self.threads.push(thread);
+    }
+
+    /// Adds The Calling CPU, Which Starts Out Running `current` Until The
+    /// First Tick & Falls Back To `idle`.
+    fn add_cpu(&mut self, current: usize, idle: usize) {
+        self.cpus.push(CpuSlot {
+            cpu: smp::index(),
+            current,
+            idle,
+            previous: None,
+            slice_start: pit::uptime(),
+        });
+    }
+
+    /// The Calling CPU's Slot, `None` Before It Joins The Scheduler.
+    fn slot(&self) -> Option<usize> {
+        let cpu = smp::index();
+        self.cpus.iter().position(|slot| slot.cpu == cpu)
+    }
+
+    fn current_index(&self) -> usize {
+        let slot = self.slot().expect("CPU Isn't Scheduling");
+        self.cpus[slot].current
+    }
+
+    /// The Thread Running On The Calling CPU.
+    pub fn current(&self) -> &Thread {
+        &self.threads[self.current_index()]
+    }
+
+    pub fn current_mut(&mut self) -> &mut Thread {
+        let index = self.current_index();
+        &mut self.threads[index]
+    }
+
+    pub fn find(&self, id: ThreadId) -> Option<usize> {
+        self.threads.iter().position(|thread| thread.id() == id)
+    }
+
+    pub fn get_mut(&mut self, id: ThreadId) -> Option<&mut Thread> {
+        self.threads.iter_mut().find(|thread| thread.id() == id)
+    }
+
+    pub fn threads(&self) -> &[Thread] {
+        &self.threads
+    }
+
+    /// Removes `id` If It Has Exited & No CPU Is Still On Its Stack,
+    /// Returning Its Exit Code.
+    pub fn reap(&mut self, id: ThreadId) -> Option<u8> {
+        let index = self.find(id)?;
+        if self.is_in_use(index) {
+            return None;
+        }
+        if let State::Exited(code) = self.threads[index].state() {
+            self.remove(index);
+            Some(code)
+        } else {
+            None
+        }
+    }
+
+    /// Frees Every Detached Thread That Has Exited.
+    pub fn reap_detached(&mut self) {
+        let mut index = 0;
+        while index < self.threads.len() {
+            let thread = &self.threads[index];
+            if !self.is_in_use(index) && thread.is_detached() && thread.has_exited() {
+                self.remove(index);
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.threads.remove(index);
+        for slot in &mut self.cpus {
+            if index < slot.current {
+                slot.current -= 1;
+            }
+            if index < slot.idle {
+                slot.idle -= 1;
+            }
+        }
+    }
+
+    /// Whether Any CPU Is Running, Idling On Or Leaving The Thread.
+    fn is_in_use(&self, index: usize) -> bool {
+        let id = self.threads[index].id();
+        self.cpus.iter().any(|slot| {
+            slot.current == index || slot.idle == index || slot.previous == Some(id)
+        })
+    }
+
+    /// Whether `id` Is In Use By Any CPU, `false` Once It Was Removed.
+    pub fn is_on_cpu(&self, id: ThreadId) -> bool {
+        self.find(id).is_some_and(|index| self.is_in_use(index))
+    }
+
+    /// Whether The CPU In `slot` May Switch To The Thread, Which Mustn't Be
+    /// An Idle Thread Or In Use By Another CPU.
+    fn is_free_for(&self, slot: usize, index: usize) -> bool {
+        let id = self.threads[index].id();
+        self.cpus.iter().enumerate().all(|(other, cpu)| {
+            cpu.idle != index
+                && (other == slot || (cpu.current != index && cpu.previous != Some(id)))
+        })
+    }
+
+    fn is_runnable(&self, index: usize, now: u64) -> bool {
+        match self.threads[index].state() {
+            State::Ready | State::Running => true,
+            State::Sleeping(until) => now >= until,
+            State::Joining(id) => self
+                .find(id)
+                .is_none_or(|target| self.threads[target].has_exited()),
+            State::Exited(_) => false,
+        }
+    }
+
+    /// Saves `context` Into The Thread Running In `slot` And Returns The
+    /// Context Of The Next Runnable Thread, Falling Back To The CPU's Idle
+    /// Thread.
+    fn schedule(&mut self, slot: usize, context: *mut Context) -> *mut Context {
+        let now = pit::uptime();
+        let current = self.cpus[slot].current;
+        self.threads[current].save(context);
+        // The CPU Has Been On `current`'s Stack Since The Last Switch.
+        self.cpus[slot].previous = None;
+
+        let count = self.threads.len();
+        let mut next = self.cpus[slot].idle;
+        for offset in 1..=count {
+            let index = (current + offset) % count;
+            if self.is_free_for(slot, index) && self.is_runnable(index, now) {
+                next = index;
+                break;
+            }
+        }
+
+        if next != current {
+            self.cpus[slot].previous = Some(self.threads[current].id());
+        }
+        self.cpus[slot].current = next;
+        self.cpus[slot].slice_start = now;
+        if let Some(top) = self.threads[next].kernel_stack_top() {
+            gdt::set_kernel_stack(VirtAddr::new(top));
+        }
+        unsafe {
+            address_space::activate(self.threads[next].address_space());
+        }
+        self.threads[next].resume()
+    }
+}
+
+/// Starts Scheduling On The Boot CPU, Which Keeps Running Thread #0.
+pub fn init(idle: Thread) {
+    SCHEDULER.init_once(|| {
+        let mut scheduler = Scheduler::new();
+        let index = scheduler.threads.len();
+        scheduler.add(idle);
+        scheduler.add_cpu(0, index);
+        Locked::new(scheduler)
+    });
+}
+
+/// Adds The Calling AP To The Scheduler, The Code Calling Becomes Its Idle
+/// Thread.
+///
+/// ## Panics
+/// - Panics If The Scheduler Has Not Been Initialized.
+pub fn init_ap() {
+    with(|scheduler| {
+        let id = scheduler.next_id();
+        let index = scheduler.threads.len();
+        scheduler.add(Thread::boot(id, "idle"));
+        scheduler.add_cpu(index, index);
+    })
+    .expect("Scheduler Not Initialized");
+}
+
+pub fn is_running() -> bool {
+    SCHEDULER.get().is_some()
+}
+
+/// Runs `f` With The Scheduler Locked And Interrupts Disabled.
+pub fn with<R>(f: impl FnOnce(&mut Scheduler) -> R) -> Option<R> {
+    let scheduler = SCHEDULER.get()?;
+    Some(without_interrupts(|| f(&mut scheduler.lock())))
+}
+
+/// Called On Every Timer Tick, Preempts The Current Thread Once Its Quantum
+/// Is Used Up (Or Immediately If The Idle Thread Is Running).
+pub fn tick(context: *mut Context) -> *mut Context {
+    if let Some(scheduler) = SCHEDULER.get() {
+        if let Some(mut scheduler) = scheduler.try_lock() {
+            if let Some(slot) = scheduler.slot() {
+                let cpu = &scheduler.cpus[slot];
+                let expired = pit::uptime() - cpu.slice_start >= QUANTUM;
+                if expired || cpu.current == cpu.idle {
+                    return scheduler.schedule(slot, context);
+                }
+            }
+        }
+    }
+    context
+}
+
+/// Called From The Yield Interrupt, Always Switches To The Next Thread.
+pub fn switch(context: *mut Context) -> *mut Context {
+    if let Some(scheduler) = SCHEDULER.get() {
+        if let Some(mut scheduler) = scheduler.try_lock() {
+            if let Some(slot) = scheduler.slot() {
+                return scheduler.schedule(slot, context);
+            }
+        }
+    }
+    context
+}
+
+/// Returns `(Id, Name, State)` For Every Thread.
+pub fn list() -> Vec<(ThreadId, String, State)> {
+    with(|scheduler| {
+        scheduler
+            .threads()
+            .iter()
+            .map(|thread| (thread.id(), thread.name().into(), thread.state()))
+            .collect()
+    })
+    .unwrap_or_default()
+}
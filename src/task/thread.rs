@@ -0,0 +1,157 @@
+use core::{fmt::Display, mem::size_of};
+
+use alloc::{string::String, vec, vec::Vec};
+
+use crate::{arch::context::Context, mem::address_space::AddressSpace};
+
+/// Size Of Each Kernel Thread's Stack (64 KB).
+pub const STACK_SIZE: usize = 4096 * 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ThreadId(pub u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Ready,
+    Running,
+    /// Sleeping Until The Given PIT Uptime.
+    Sleeping(u64),
+    /// Waiting For Another Thread To Exit.
+    Joining(ThreadId),
+    Exited(u8),
+}
+
+pub struct Thread {
+    id: ThreadId,
+    name: String,
+    state: State,
+    context: *mut Context,
+    stack: Option<Vec<u8>>,
+    detached: bool,
+    /// The Address Space Loaded While The Thread Runs, Null For Kernel
+    /// Threads. Owned By The Thread's Process.
+    address_space: *mut AddressSpace,
+}
+
+impl Thread {
+    /// The Thread That Was Already Running When The Scheduler Started,
+    /// Its Context Is Filled In The First Time It Is Preempted.
+    pub fn boot(id: ThreadId, name: &str) -> Self {
+        Self {
+            id,
+            name: name.into(),
+            state: State::Running,
+            context: core::ptr::null_mut(),
+            stack: None,
+            detached: true,
+            address_space: core::ptr::null_mut(),
+        }
+    }
+
+    /// Creates A Thread That Starts Executing `entry(arg)` On A Fresh Stack.
+    pub fn new(id: ThreadId, name: &str, entry: extern "C" fn(u64) -> !, arg: u64) -> Self {
+        let stack = vec![0u8; STACK_SIZE];
+        let top = (stack.as_ptr() as u64 + STACK_SIZE as u64) & !0xF;
+
+        // The entry point is reached by `iretq` rather than `call`, so leave
+        // the stack misaligned by one return address like the ABI expects.
+        let stack_pointer = top - 8;
+        let context = (stack_pointer - size_of::<Context>() as u64) as *mut Context;
+
+        unsafe {
+            context.write(Context::kernel(entry as *const () as u64, stack_pointer, arg));
+        }
+
+        Self {
+            id,
+            name: name.into(),
+            state: State::Ready,
+            context,
+            stack: Some(stack),
+            detached: false,
+            address_space: core::ptr::null_mut(),
+        }
+    }
+
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    pub fn has_exited(&self) -> bool {
+        matches!(self.state, State::Exited(_))
+    }
+
+    pub fn is_detached(&self) -> bool {
+        self.detached
+    }
+
+    pub fn detach(&mut self) {
+        self.detached = true;
+    }
+
+    pub fn address_space(&self) -> *mut AddressSpace {
+        self.address_space
+    }
+
+    pub fn set_address_space(&mut self, space: *mut AddressSpace) {
+        self.address_space = space;
+    }
+
+    pub fn stack_size(&self) -> usize {
+        self.stack.as_ref().map_or(0, |stack| stack.len())
+    }
+
+    /// The Top Of The Thread's Kernel Stack, Loaded Into The TSS So
+    /// Interrupts From Ring 3 Land On It.
+    pub fn kernel_stack_top(&self) -> Option<u64> {
+        self.stack
+            .as_ref()
+            .map(|stack| (stack.as_ptr() as u64 + stack.len() as u64) & !0xF)
+    }
+
+    /// Stores The Context The Thread Was Interrupted With.
+    pub fn save(&mut self, context: *mut Context) {
+        self.context = context;
+        if self.state == State::Running {
+            self.state = State::Ready;
+        }
+    }
+
+    /// Marks The Thread As Running And Returns The Context To Resume.
+    pub fn resume(&mut self) -> *mut Context {
+        self.state = State::Running;
+        self.context
+    }
+}
+
+unsafe impl Send for Thread {}
+
+impl Display for ThreadId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Display for State {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            State::Ready => write!(f, "Ready"),
+            State::Running => write!(f, "Running"),
+            State::Sleeping(until) => write!(f, "Sleeping (Until {})", until),
+            State::Joining(id) => write!(f, "Joining #{}", id),
+            State::Exited(code) => write!(f, "Exited ({})", code),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Wakers Fired From Interrupt Handlers.
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::locked::Locked;
+
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeSource {
+    Timer,
+    Keyboard,
+    Ata,
+    Cmos,
+    Net,
+}
+
+const SOURCE_COUNT: usize = 5;
+
+static COUNTERS: [AtomicU64; SOURCE_COUNT] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+lazy_static! {
+    static ref WAKERS: [Locked<Vec<Waker>>; SOURCE_COUNT] = [
+        Locked::new(Vec::new()),
+        Locked::new(Vec::new()),
+        Locked::new(Vec::new()),
+        Locked::new(Vec::new()),
+        Locked::new(Vec::new()),
+    ];
+
+    /// Timer Wakers Paired With The PIT Uptime They Are Waiting For.
+    static ref DEADLINES: Locked<Vec<(u64, Waker)>> = Locked::new(Vec::new());
+}
+
+/// Wakes `waker` On The Next Interrupt From `source`.
+pub fn register(source: WakeSource, waker: &Waker) {
+    without_interrupts(|| {
+        let mut wakers = WAKERS[source as usize].lock();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    });
+}
+
+/// Wakes `waker` Once The PIT Uptime Reaches `deadline`. A Waker Already
+/// Waiting Keeps The Earlier Of Its Deadlines.
+pub fn register_deadline(deadline: u64, waker: &Waker) {
+    without_interrupts(|| {
+        let mut deadlines = DEADLINES.lock();
+        match deadlines.iter_mut().find(|(_, w)| w.will_wake(waker)) {
+            Some(entry) => entry.0 = entry.0.min(deadline),
+            None => deadlines.push((deadline, waker.clone())),
+        }
+    });
+}
+
+/// Number Of Interrupts `source` Has Raised.
+pub fn count(source: WakeSource) -> u64 {
+    COUNTERS[source as usize].load(Ordering::SeqCst)
+}
+
+/// Fires Every Waker Registered For `source`, Called From Interrupt Handlers.
+///
+/// The Locks Are Only Ever Held With Interrupts Disabled, So Waiting For One
+/// Means Waiting For Another CPU To Finish Registering, Never Deadlocking.
+pub fn wake(source: WakeSource) {
+    COUNTERS[source as usize].fetch_add(1, Ordering::SeqCst);
+
+    without_interrupts(|| {
+        for waker in WAKERS[source as usize].lock().drain(..) {
+            waker.wake();
+        }
+    });
+
+    if source == WakeSource::Timer {
+        wake_deadlines(crate::pit::uptime());
+    }
+}
+
+fn wake_deadlines(now: u64) {
+    without_interrupts(|| {
+        let mut deadlines = DEADLINES.lock();
+        let mut index = 0;
+        while index < deadlines.len() {
+            if deadlines[index].0 <= now {
+                deadlines.swap_remove(index).1.wake();
+            } else {
+                index += 1;
+            }
+        }
+    });
+}
+
+/// A Future That Completes On The Next Interrupt From A Source, Or Once
+/// Its Deadline Passes If It Has One.
+pub struct Interrupt {
+    source: WakeSource,
+    start: u64,
+    deadline: Option<u64>,
+}
+
+impl Interrupt {
+    /// Also Completes After `millis` Milliseconds Without An Interrupt.
+    pub fn timeout(mut self, millis: u64) -> Self {
+        self.deadline = Some(crate::pit::uptime().saturating_add(millis));
+        self
+    }
+
+    fn is_done(&self) -> bool {
+        count(self.source) != self.start
+            || self.deadline.is_some_and(|deadline| crate::pit::uptime() >= deadline)
+    }
+}
+
+impl Future for Interrupt {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.is_done() {
+            return Poll::Ready(());
+        }
+
+        register(self.source, cx.waker());
+        if let Some(deadline) = self.deadline {
+            register_deadline(deadline, cx.waker());
+        }
+
+        // The interrupt may have fired while the waker was being registered.
+        if self.is_done() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Waits For The Next Interrupt From `source`, Counting From Now.
+pub fn interrupt(source: WakeSource) -> Interrupt {
+    Interrupt {
+        source,
+        start: count(source),
+        deadline: None,
+    }
+}
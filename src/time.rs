@@ -2,6 +2,7 @@
 
 use crate::{
     arch::cmos::{self, CMOS},
+    clock,
     csh::{ExitCode, ShellArgs},
     println,
 };
@@ -73,8 +74,9 @@ pub fn ticks() -> usize {
     unsafe { RTC_TICKS }
 }
 
+/// Seconds Since Boot, From The Monotonic Clock.
 pub fn seconds() -> f64 {
-    unsafe { (RTC_TICKS as f64) / (UPDATE_FREQ as f64) + ((RTC_TICKS % UPDATE_FREQ) as f64) / UPDATE_FREQ as f64 }
+    clock::monotonic_ns() as f64 / 1_000_000_000.0
 }
 
 
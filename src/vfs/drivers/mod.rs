@@ -2,6 +2,8 @@
 
 use alloc::{boxed::Box, string::String, vec::Vec};
 
+use crate::device::BlockAddr;
+
 
 pub mod ustar;
 pub mod simple_fat;
@@ -11,6 +13,24 @@ pub mod disk_map;
 
 pub trait VirtFileSystem {
     fn open_file(&self, filename: &str) -> Option<Box<dyn FileIO>>;
+
+    /// Where A File's Data Lives On The Mounted Device, So It Can Be Mapped
+    /// Without Reading It Whole. `None` If The File Doesn't Exist Or Isn't
+    /// Stored In Consecutive Blocks.
+    fn locate(&self, _filename: &str) -> Option<FileExtent> {
+        None
+    }
+}
+
+/// A File Stored In Consecutive Blocks Of The Mounted Device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileExtent {
+    /// The Block Holding The First Byte.
+    pub start: BlockAddr,
+    /// Size In Bytes.
+    pub size: usize,
+    /// Whether Blocks May Be Written Back In Place.
+    pub writable: bool,
 }
 
 pub trait FileRead {
@@ -2,13 +2,13 @@ use core::{ops::{Index, IndexMut, Range, Sub, Add}, fmt::{Display, Debug}};
 
 use alloc::{string::{String, ToString}, vec::{Vec}};
 
-use crate::{ata::BLOCK_SIZE, api::fs::Block, klog, device};
+use crate::{ata::BLOCK_SIZE, api::fs::Block, klog, device, mem::mmap};
 
 use super::PhysicalBlockAddr;
 /// The Size Of A Single Entry
 pub const ENTRY_SIZE: usize = 32;
 pub const ENTRIES_PER_BLOCK: usize = BLOCK_SIZE / ENTRY_SIZE;
-pub const ENTRY_BIT_OFFSET: usize = usize::log2(ENTRY_SIZE) as usize;
+pub const ENTRY_BIT_OFFSET: usize = ENTRY_SIZE.ilog2() as usize;
 
 
 /// 00..16: FileName,
@@ -141,12 +141,15 @@ impl FileEntry {
         self.is_empty = true;
     }
 
-    pub fn set_data(&mut self, data: &[u8]) {
+    /// Writes `data` As The Entry's Contents, Returning `false` Without
+    /// Writing If The File Is Mapped, As Its Blocks Would Move.
+    pub fn set_data(&mut self, data: &[u8]) -> bool {
+        let mut fat = super::FILE_SYSTEM.lock();
+        if mmap::is_pinned(fat[self.index].block_addr_range()) {
+            return false;
+        }
         self.size = data.len().try_into().expect("Failed To Cast usize -> u32");
 
-    
-
-        let mut fat = super::FILE_SYSTEM.lock();
         if self.size > 0 {
             if let Some(new_begin) = fat.find_free_range(data.len()) {
                 self.begin = new_begin;
@@ -162,6 +165,7 @@ impl FileEntry {
         fat[self.index] = self.clone();
 
         fat.write();
+        true
     }
 
     pub fn size(&self) -> usize {
@@ -317,6 +321,21 @@ impl FileAttributeTable {
         return None;
     }
 
+    /// Whether An Entry Is Stored In One Run Of Blocks Only It Uses, So It
+    /// Can Be Addressed As A Single Extent.
+    pub fn is_contiguous(&self, entry: &FileEntry) -> bool {
+        let range = entry.block_addr_range();
+        if entry.is_empty() || range.end as usize > device::blk_dev_size() {
+            return false;
+        }
+
+        !self.entries.iter().any(|other| {
+            other.index() != entry.index()
+                && !other.is_empty()
+                && range_overlaps(&other.block_addr_range(), &range)
+        })
+    }
+
     fn bytes_to_blocks(size: usize) -> usize {
         let mut blocks = size / BLOCK_SIZE;
         if size % BLOCK_SIZE > 0 {
@@ -1,14 +1,14 @@
 pub type PhysicalBlockAddr = u32;
 pub type VirtualBlockAddr = u32;
 pub mod fat;
-use alloc::{vec::Vec, string::String};
+use alloc::{vec::Vec, string::String, boxed::Box};
 use lazy_static::lazy_static;
 use fat::FileAttributeTable;
-use crate::{locked::Locked};
+use crate::{kerr, locked::Locked, mem::mmap};
 
 use self::fat::FileEntry;
 
-use super::{FileIO, FileWrite, FileRead, FileAppend};
+use super::{FileIO, FileWrite, FileRead, FileAppend, FileExtent, VirtFileSystem};
 lazy_static! {
     static ref FILE_SYSTEM: Locked<fat::FileAttributeTable> = Locked::new(FileAttributeTable::load(0, 4));
 }
@@ -21,17 +21,41 @@ pub struct File {
     pos: usize,
 }
 
-pub fn delete_file(name: &str) -> bool {
-    let mut fat = FILE_SYSTEM.lock();
-    match fat.search_for_file_mut(name) {
-        None => false,
-        Some(entry) => {
-            entry.erase();
-            true
+pub struct FileSystem;
+
+impl VirtFileSystem for FileSystem {
+    fn open_file(&self, filename: &str) -> Option<Box<dyn FileIO>> {
+        load_file(filename).map(|file| Box::new(file) as Box<dyn FileIO>)
+    }
+
+    fn locate(&self, filename: &str) -> Option<FileExtent> {
+        let fat = FILE_SYSTEM.lock();
+        let entry = fat.search_for_file(filename)?;
+        if !fat.is_contiguous(entry) {
+            return None;
         }
+        Some(FileExtent {
+            start: entry.block_addr_range().start,
+            size: entry.size(),
+            writable: true,
+        })
     }
 }
 
+/// Deletes `name`, Returning `false` If It Doesn't Exist Or Is Mapped.
+pub fn delete_file(name: &str) -> bool {
+    // `erase` Writes The Table Back Itself, So It Can't Run Under The Lock.
+    let mut entry = {
+        let fat = FILE_SYSTEM.lock();
+        match fat.search_for_file(name) {
+            Some(entry) if !mmap::is_pinned(entry.block_addr_range()) => entry.clone(),
+            _ => return false,
+        }
+    };
+    entry.erase();
+    true
+}
+
 pub fn load_file(name: &str) -> Option<File> {
     let fat = FILE_SYSTEM.lock();
     
@@ -120,7 +144,9 @@ impl FileAppend for File {
 
 impl FileIO for File {
     fn close(&mut self) {
-        self.entry.set_data(&self.data)
+        if !self.entry.set_data(&self.data) {
+            kerr!("Can't Write {} While It Is Mapped\n", self.entry.name());
+        }
     }
 
     fn size(&self) -> usize {
@@ -8,7 +8,7 @@ use crate::{
 };
 
 
-use super::{FileIO, FileWrite, FileRead, VirtFileSystem, FileAppend};
+use super::{FileIO, FileWrite, FileRead, VirtFileSystem, FileAppend, FileExtent};
 
 #[derive(PartialEq, Debug, Eq, PartialOrd, Ord, Clone, Copy, Default, Hash)]
 #[repr(u8)]
@@ -53,6 +53,10 @@ impl VirtFileSystem for FileSystem {
             return None;
         }
     }
+
+    fn locate(&self, filename: &str) -> Option<FileExtent> {
+        FileInfo::locate(filename).ok()
+    }
 }
 
 #[derive(Debug)]
@@ -101,6 +105,30 @@ impl FileInfo {
         Err(())
     }
 
+    /// Finds A File By Reading Only The Headers, Its Data Starts In The
+    /// Block After Its Header.
+    pub fn locate(name: &str) -> Result<FileExtent, ()> {
+        let max = device::info()?.blocks;
+        let mut address = 0;
+
+        while address < max {
+            let header = Block::read(address as u32).ok_or(())?;
+            let (entry_name, size, filetype) = Self::parse_header(&header);
+
+            if entry_name.eq(name) && filetype == FileType::Normal {
+                return Ok(FileExtent {
+                    start: address as u32 + 1,
+                    size: size as usize,
+                    writable: false,
+                });
+            }
+
+            address += (size as usize).div_ceil(512) + 1;
+        }
+
+        Err(())
+    }
+
     pub fn size(&self) -> usize {
         self.size as usize
     }
@@ -118,6 +146,23 @@ impl FileInfo {
 
         //sprint!("Loaded Block #{}\n",addr);
 
+        let (name, size, filetype) = Self::parse_header(&info);
+        let mut blocks = Vec::new();
+        let block_len = (size / 512) + if size % 512 > 0 { 1 } else { 0 };
+
+        for i in 1..=block_len {
+            blocks.push(Block::read(addr + i).unwrap());
+        }
+        Ok(Self {
+            name,
+            blocks,
+            size,
+            filetype,
+        })
+    }
+
+    /// Reads The Name, Size & Type Out Of A Header Block.
+    fn parse_header(info: &Block) -> (String, u32, FileType) {
         let mut name_end = 0;
 
         for _ in 0..100 {
@@ -133,22 +178,12 @@ impl FileInfo {
         //sprint!("Size: '{}'\n", size);
         let size: u32 = u32::from_str_radix(&size, 8).unwrap_or(0);
         //sprint!("Size: {} Bytes\n", size);
-        let mut blocks = Vec::new();
-        let block_len = (size / 512) + if size % 512 > 0 { 1 } else { 0 };
 
         let ty: u8 =
             u8::from_str_radix(&String::from_utf8_lossy(&info.data()[156..157].to_vec()), 8)
                 .unwrap_or(255);
 
-        for i in 1..=block_len {
-            blocks.push(Block::read(addr + i).unwrap());
-        }
-        Ok(Self {
-            name,
-            blocks,
-            size,
-            filetype: FileType::from_u8(ty),
-        })
+        (name, size, FileType::from_u8(ty))
     }
 
     pub fn blocks(&self) -> &Vec<Block> {